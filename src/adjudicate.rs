@@ -0,0 +1,245 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use mintymacks::{game::GameState, model::Color, notation::fen::render_fen};
+use serde::{Deserialize, Serialize};
+use shakmaty::{CastlingMode, Chess, Position, fen::Fen};
+use shakmaty_syzygy::{Tablebase, Wdl};
+
+use crate::search_info::Score;
+
+/// Rules for ending games early, all off unless given.
+#[derive(Args, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AdjudicationRules {
+    /// Resign for the losing side once both engines agree on a score of at
+    /// least this many centipawns
+    #[clap(long)]
+    pub resign_score: Option<i32>,
+
+    /// Consecutive moves of each engine the resign score must hold for
+    #[clap(long, default_value_t = 3)]
+    pub resign_moves: usize,
+
+    /// Declare a draw once both engines report a score within this many
+    /// centipawns of zero
+    #[clap(long)]
+    pub draw_score: Option<i32>,
+
+    /// Consecutive moves of each engine the draw score must hold for
+    #[clap(long, default_value_t = 8)]
+    pub draw_moves: usize,
+
+    /// First move number at which draws are adjudicated
+    #[clap(long, default_value_t = 40)]
+    pub draw_after: usize,
+
+    /// Directory of Syzygy tablebases to adjudicate positions they cover
+    #[clap(long)]
+    pub tablebase: Option<PathBuf>,
+}
+
+/// An early result, with the reason recorded in the PGN.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adjudication {
+    pub winner: Option<Color>,
+    pub reason: String,
+}
+
+pub struct Adjudicator {
+    pub rules: AdjudicationRules,
+    pub tablebase: Option<Tablebase<Chess>>,
+}
+
+impl Adjudicator {
+    pub fn new(rules: AdjudicationRules) -> tokio::io::Result<Self> {
+        let tablebase = match &rules.tablebase {
+            Some(dir) => {
+                let mut tb = Tablebase::new();
+                tb.add_directory(dir)?;
+                Some(tb)
+            }
+            None => None,
+        };
+
+        Ok(Adjudicator { rules, tablebase })
+    }
+
+    /// Checks the rules after a move. `scores` holds the score reported for
+    /// every move so far, from white's point of view.
+    pub fn check(&self, game: &GameState, scores: &[Option<Score>]) -> Option<Adjudication> {
+        self.tablebase(game)
+            .or_else(|| self.resign(scores))
+            .or_else(|| self.draw(scores))
+    }
+
+    fn recent(scores: &[Option<Score>], moves: usize) -> Option<Vec<i32>> {
+        if moves == 0 || scores.len() < 2 * moves {
+            return None;
+        }
+
+        scores[scores.len() - 2 * moves..]
+            .iter()
+            .map(|s| s.map(Score::cp))
+            .collect()
+    }
+
+    fn resign(&self, scores: &[Option<Score>]) -> Option<Adjudication> {
+        let threshold = self.rules.resign_score?;
+        let recent = Self::recent(scores, self.rules.resign_moves)?;
+
+        let (winner, loser) = if recent.iter().all(|cp| *cp >= threshold) {
+            (Color::White, "Black")
+        } else if recent.iter().all(|cp| *cp <= -threshold) {
+            (Color::Black, "White")
+        } else {
+            return None;
+        };
+
+        Some(Adjudication {
+            winner: Some(winner),
+            reason: format!(
+                "{loser} resigns, both engines scored beyond {threshold}cp for {} moves",
+                self.rules.resign_moves
+            ),
+        })
+    }
+
+    fn draw(&self, scores: &[Option<Score>]) -> Option<Adjudication> {
+        let threshold = self.rules.draw_score?;
+        if scores.len() / 2 + 1 < self.rules.draw_after {
+            return None;
+        }
+        let recent = Self::recent(scores, self.rules.draw_moves)?;
+
+        if !recent.iter().all(|cp| cp.abs() < threshold) {
+            return None;
+        }
+
+        Some(Adjudication {
+            winner: None,
+            reason: format!(
+                "Draw, both engines scored within {threshold}cp for {} moves",
+                self.rules.draw_moves
+            ),
+        })
+    }
+
+    fn tablebase(&self, game: &GameState) -> Option<Adjudication> {
+        let tb = self.tablebase.as_ref()?;
+
+        let board = game.board.render();
+        let pieces = (&board).into_iter().filter(|(_, pc)| pc.is_some()).count();
+        if pieces > tb.max_pieces() {
+            return None;
+        }
+
+        let fen = render_fen(&game.board);
+        let pos: Chess = Fen::from_ascii(fen.as_bytes())
+            .ok()?
+            .into_position(CastlingMode::Standard)
+            .ok()?;
+
+        let to_move = match pos.turn() {
+            shakmaty::Color::White => Color::White,
+            shakmaty::Color::Black => Color::Black,
+        };
+        let other = match to_move {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        };
+
+        // counts the fifty-move rule in, and gives up on positions too
+        // close to it to tell, or whose tables are missing, until a later
+        // move
+        let winner = match tb.probe_wdl(&pos).ok()?.unambiguous()? {
+            Wdl::Win => Some(to_move),
+            Wdl::Loss => Some(other),
+            Wdl::Draw | Wdl::CursedWin | Wdl::BlessedLoss => None,
+        };
+
+        Some(Adjudication {
+            winner,
+            reason: format!(
+                "Tablebase {}",
+                if winner.is_some() { "win" } else { "draw" }
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adjudicator(rules: AdjudicationRules) -> Adjudicator {
+        Adjudicator::new(rules).unwrap()
+    }
+
+    fn resigning() -> AdjudicationRules {
+        AdjudicationRules {
+            resign_score: Some(500),
+            resign_moves: 2,
+            ..Default::default()
+        }
+    }
+
+    fn drawing() -> AdjudicationRules {
+        AdjudicationRules {
+            draw_score: Some(10),
+            draw_moves: 1,
+            draw_after: 3,
+            ..Default::default()
+        }
+    }
+
+    fn cp(scores: &[i32]) -> Vec<Option<Score>> {
+        scores.iter().map(|cp| Some(Score::Cp(*cp))).collect()
+    }
+
+    #[test]
+    fn resign_needs_both_engines() {
+        let adj = adjudicator(resigning());
+        assert_eq!(adj.resign(&cp(&[0, 600, 700, 600])), None);
+        assert_eq!(
+            adj.resign(&cp(&[0, 600, 700, 600, 800])).unwrap().winner,
+            Some(Color::White)
+        );
+        assert_eq!(
+            adj.resign(&cp(&[-500, -600, -700, -500])).unwrap().winner,
+            Some(Color::Black)
+        );
+    }
+
+    #[test]
+    fn resign_counts_mates() {
+        let adj = adjudicator(resigning());
+        let scores = [Score::Cp(900), Score::Mate(3), Score::Mate(2), Score::Mate(2)];
+        let scores = scores.map(Some);
+        assert_eq!(adj.resign(&scores).unwrap().winner, Some(Color::White));
+    }
+
+    #[test]
+    fn missing_scores_hold_off() {
+        let adj = adjudicator(resigning());
+        let mut scores = cp(&[600, 600, 600, 600]);
+        scores[2] = None;
+        assert_eq!(adj.resign(&scores), None);
+    }
+
+    #[test]
+    fn draw_waits_for_the_move_number() {
+        let adj = adjudicator(drawing());
+        assert_eq!(adj.draw(&cp(&[5, -5, 0])), None);
+        assert_eq!(adj.draw(&cp(&[5, -5, 0, 5])).unwrap().winner, None);
+        assert_eq!(adj.draw(&cp(&[0, 5, -5, 10])), None);
+    }
+
+    #[test]
+    fn rules_off_by_default() {
+        let adj = adjudicator(AdjudicationRules::default());
+        let scores = cp(&[0; 200]);
+        assert_eq!(adj.check(&GameState::startpos(), &scores), None);
+        let scores = cp(&[10_000; 200]);
+        assert_eq!(adj.check(&GameState::startpos(), &scores), None);
+    }
+}
@@ -0,0 +1,515 @@
+use std::{collections::VecDeque, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, builder::RangedU64ValueParser};
+use crossterm::{
+    cursor,
+    event::{Event, EventStream, KeyCode},
+    queue,
+    style::{self, ContentStyle, Stylize},
+    terminal,
+};
+use mintymacks::{
+    bits::bit,
+    engine::EngineHandle,
+    eprintln_async,
+    game::GameState,
+    model::{
+        Color,
+        moves::{ChessMove, SpecialMove},
+    },
+    notation::{
+        fen::render_fen,
+        pgn::load_pgn_file,
+        uci::{
+            engine::UciEngine,
+            gui::{GoCommand, UciGui},
+        },
+    },
+};
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    select,
+    time::sleep,
+};
+use tokio_stream::StreamExt;
+
+use crate::{
+    Runnable,
+    faceoff::{Contestant, read_profile},
+    keymap::{Action, Keymap},
+    search_info::SearchInfo,
+    widgets::{
+        self, TextRenderer,
+        annotations::{Annotations, Arrow, Tint, parse_square},
+        board::BoardRenderer,
+        move_input::lan,
+        move_select::{MoveSelect, game_from_fen, legal_moves},
+    },
+};
+
+#[derive(Parser)]
+pub struct AnalyzePosition {
+    /// Engine profile
+    pub profile: PathBuf,
+
+    /// Position to analyze, instead of the starting position
+    #[clap(long, conflicts_with = "pgn")]
+    pub fen: Option<String>,
+
+    /// Analyze the final position of a game from a PGN file
+    #[clap(long)]
+    pub pgn: Option<PathBuf>,
+
+    /// Game in the PGN file, counting from 1
+    #[clap(long, default_value_t = 1)]
+    pub game: usize,
+
+    /// Number of lines to show
+    #[clap(long, default_value_t = 3, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub lines: usize,
+}
+
+impl Runnable for AnalyzePosition {
+    async fn run(self) -> tokio::io::Result<()> {
+        let root = match (&self.fen, &self.pgn) {
+            (Some(fen), _) => game_from_fen(fen),
+            (None, Some(path)) => {
+                let text = String::from_utf8_lossy_owned(tokio::fs::read(path).await?);
+                match load_pgn_file(&text)
+                    .into_iter()
+                    .nth(self.game.saturating_sub(1))
+                {
+                    Some(pgn) => GameState::from_pgn(&pgn).map_err(|e| e.to_string()),
+                    None => Err(format!("No game #{} in file", self.game)),
+                }
+            }
+            (None, None) => Ok(GameState::startpos()),
+        };
+
+        let root = match root {
+            Err(s) => {
+                eprintln_async!("Error in loading position: {}", s).await;
+                ExitCode::FAILURE.exit_process();
+            }
+            Ok(g) => g,
+        };
+
+        let mut profile = read_profile(&self.profile).await?;
+        profile.options.insert(
+            String::from("MultiPV"),
+            toml::Value::Integer(self.lines as i64),
+        );
+
+        let mut contestant = Contestant::from_profile(profile).await?;
+        if let Err(reason) = contestant.new_game().await? {
+            eprintln_async!("Engine not ready: {reason}").await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        let mut analyzer = PositionAnalyzer {
+            name: contestant.profile.engine.name.clone(),
+            root,
+            history: vec![],
+            lines: vec![None; self.lines],
+            outbox: VecDeque::new(),
+            searching: false,
+            stopping: 0,
+            rotated: false,
+            frame: false,
+            help: false,
+            status: String::new(),
+            select: MoveSelect::default(),
+        };
+
+        analyzer.mainloop(&mut contestant.engine).await?;
+        contestant.engine.quit().await?;
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+/// Plays a move given in long algebraic notation, returning it in SAN.
+pub fn play_lan(game: &mut GameState, mv: &str) -> Option<String> {
+    let mv = legal_moves(&game.board)
+        .into_iter()
+        .find(|m| lan(*m) == mv)?;
+    let promotion = match mv.spc {
+        Some(SpecialMove::Promotion(pc)) => Some(pc),
+        _ => None,
+    };
+    let fm = game.find_move((mv.pmv, promotion)).ok()?;
+    Some(game.apply(fm).unwrap().algebraic.to_string())
+}
+
+/// A principal variation in SAN, up to the first move that doesn't apply.
+pub fn pv_san(game: &GameState, pv: &[String]) -> Vec<String> {
+    let mut game = game.clone();
+    pv.iter().map_while(|mv| play_lan(&mut game, mv)).collect()
+}
+
+pub struct PositionAnalyzer {
+    pub name: String,
+    pub root: GameState,
+    /// Earlier roots, to step back out of a line
+    pub history: Vec<GameState>,
+    /// Latest information for each line, by `multipv` index
+    pub lines: Vec<Option<SearchInfo>>,
+    pub outbox: VecDeque<UciGui>,
+    pub searching: bool,
+    /// Searches stopped whose `bestmove` has not arrived yet
+    pub stopping: usize,
+    pub rotated: bool,
+    pub frame: bool,
+    pub help: bool,
+    pub status: String,
+    pub select: MoveSelect,
+}
+
+impl PositionAnalyzer {
+    pub const ACTIONS: [Action; 7] = [
+        Action::PrevMove,
+        Action::NextMove,
+        Action::Rotate,
+        Action::Frame,
+        Action::Help,
+        Action::Suspend,
+        Action::Quit,
+    ];
+
+    pub const TINTS: [Tint; 4] = [Tint::Green, Tint::Blue, Tint::Yellow, Tint::Red];
+
+    pub fn board_render(&self) -> BoardRenderer {
+        BoardRenderer {
+            col: 3,
+            row: 2,
+            rotated: self.rotated,
+            frame: self.frame,
+        }
+    }
+
+    pub fn title_renderer(&self) -> TextRenderer {
+        TextRenderer {
+            col: 3,
+            row: 1,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn lines_renderer(&self) -> TextRenderer {
+        TextRenderer {
+            col: 3 + 5 * 8 + 1,
+            row: 2,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub const GREY: style::Color = style::Color::Rgb {
+        r: 0x88,
+        g: 0x88,
+        b: 0x88,
+    };
+
+    pub fn reminder_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 2 + 8 * 3 + 2,
+            col: 3,
+            style: ContentStyle::new().with(Self::GREY),
+        }
+    }
+
+    pub fn status_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 2 + 8 * 3 + 1,
+            col: 3 + 5 * 8 + 1,
+            style: ContentStyle::new().with(Self::GREY),
+        }
+    }
+
+    pub fn help_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 3,
+            col: 5,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn help_text() -> String {
+        format!(
+            "{}\n\n{:<24}{}",
+            Keymap::active().help(&Self::ACTIONS),
+            "[1] to [9]",
+            "step into line",
+        )
+    }
+
+    /// Restarts the search from the current root.
+    pub fn search(&mut self) {
+        if self.searching {
+            self.outbox.push_back(UciGui::Stop());
+            self.stopping += 1;
+        }
+
+        self.outbox.push_back(UciGui::Position(
+            self.root.uci_position(),
+            self.root.uci_line(),
+        ));
+        self.outbox.push_back(UciGui::Go(GoCommand::Infinite()));
+        self.searching = true;
+
+        for line in &mut self.lines {
+            *line = None;
+        }
+        self.select.reset();
+    }
+
+    /// Makes the first move of a line the new root.
+    pub fn step(&mut self, ix: usize) {
+        let Some(Some(info)) = self.lines.get(ix) else {
+            return;
+        };
+        let Some(first) = info.pv.first() else {
+            return;
+        };
+
+        let mut next = self.root.clone();
+        if play_lan(&mut next, first).is_some() {
+            self.history.push(std::mem::replace(&mut self.root, next));
+            self.search();
+        }
+    }
+
+    /// Makes a move entered on the board the new root.
+    pub fn play(&mut self, mv: ChessMove) {
+        let mut next = self.root.clone();
+        if play_lan(&mut next, &lan(mv)).is_some() {
+            self.history.push(std::mem::replace(&mut self.root, next));
+            self.search();
+        }
+    }
+
+    pub fn back(&mut self) {
+        if let Some(prev) = self.history.pop() {
+            self.root = prev;
+            self.search();
+        }
+    }
+
+    pub fn receive(&mut self, msg: UciEngine) {
+        match msg {
+            UciEngine::BestMove(_) => self.stopping = self.stopping.saturating_sub(1),
+            // output of a stopped search is stale
+            other if self.stopping == 0 => {
+                let Some(info) = SearchInfo::from_uci(&other) else {
+                    return;
+                };
+                let ix = info.multipv.unwrap_or(1).saturating_sub(1) as usize;
+                if ix < self.lines.len() && info.score.is_some() && !info.pv.is_empty() {
+                    self.lines[ix] = Some(info);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Read from the FEN, so that it is known in mate and stalemate too.
+    pub fn side_to_move(&self) -> Color {
+        match render_fen(&self.root.board).split_whitespace().nth(1) {
+            Some("b") => Color::Black,
+            _ => Color::White,
+        }
+    }
+
+    pub fn render_line(&self, ix: usize, info: &Option<SearchInfo>) -> String {
+        let Some(info) = info else {
+            return format!("{}. ...", ix + 1);
+        };
+
+        // scores are shown from white's point of view
+        let score = match (info.score, self.side_to_move()) {
+            (Some(s), Color::Black) => s.negate().to_string(),
+            (Some(s), Color::White) => s.to_string(),
+            (None, _) => String::from("?"),
+        };
+
+        let mut res = format!("{}. {:>6}", ix + 1, score);
+        if let Some(depth) = info.depth {
+            res += &format!(" d{depth}");
+        }
+        if let Some((w, d, l)) = info.wdl {
+            res += &format!(" W{} D{} L{}", w / 10, d / 10, l / 10);
+        }
+
+        let mut pv = pv_san(&self.root, &info.pv);
+        pv.truncate(10);
+        res += "\n   ";
+        res += &pv.join(" ");
+        res
+    }
+
+    pub async fn render(&self) -> tokio::io::Result<()> {
+        let mut res = vec![];
+
+        queue!(res, terminal::Clear(terminal::ClearType::Purge));
+
+        // the title, lines and status change length, so their rows are
+        // cleared instead of the whole screen
+        let title = self.title_renderer();
+        let lines = self.lines_renderer();
+        let status = Self::status_renderer();
+        for (col, row) in [(title.col, title.row), (status.col, status.row)]
+            .into_iter()
+            .chain((lines.row..lines.row + 2 * self.lines.len() as u16).map(|row| (lines.col, row)))
+        {
+            queue!(
+                res,
+                cursor::MoveTo(col, row),
+                terminal::Clear(terminal::ClearType::UntilNewLine)
+            );
+        }
+
+        let annotations = Annotations {
+            arrows: self
+                .lines
+                .iter()
+                .zip(Self::TINTS.iter().cycle())
+                .filter_map(|(line, tint)| {
+                    let first = line.as_ref()?.pv.first()?;
+                    Some(Arrow {
+                        from: parse_square(first.get(0..2)?)?,
+                        to: parse_square(first.get(2..4)?)?,
+                        tint: *tint,
+                    })
+                })
+                .collect(),
+            marks: vec![],
+        };
+
+        let mut board = self.root.board.render();
+        let moves = legal_moves(&self.root.board);
+        let mut selectable = self.select.selectable(&moves);
+        if let Some(picker) = self.select.show_promotion(&mut board) {
+            selectable = picker;
+        }
+
+        res.append(&mut self.board_render().render_annotated(
+            &board,
+            bit(self.select.origin),
+            selectable,
+            &annotations,
+        ));
+
+        res.append(&mut self.title_renderer().render(&format!(
+            "Analysis by {}, {} lines, {} plies from the analyzed position",
+            self.name,
+            self.lines.len(),
+            self.history.len(),
+        )));
+
+        res.append(
+            &mut self.lines_renderer().render(
+                &self
+                    .lines
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, info)| self.render_line(ix, info))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        );
+
+        let keymap = Keymap::active();
+        res.append(&mut Self::reminder_renderer().render(&format!(
+            "{}: help\n{}: exit",
+            keymap.describe(Action::Help),
+            keymap.describe(Action::Quit),
+        )));
+
+        res.append(&mut Self::status_renderer().render(&self.status));
+
+        if self.help {
+            res.append(&mut Self::help_renderer().render_boxed("Keys", &Self::help_text()));
+        }
+
+        stdout().write_all(&res[..]).await?;
+
+        Ok(())
+    }
+
+    pub fn handle(&mut self, ev: Event) -> bool {
+        match ev {
+            Event::Key(key_event) if key_event.is_press() || key_event.is_repeat() => {
+                if self.help {
+                    self.help = false;
+                    // the help box covers more than the redrawn panes
+                    let _ = crossterm::execute!(
+                        std::io::stdout(),
+                        terminal::Clear(terminal::ClearType::All)
+                    );
+                    return false;
+                }
+
+                if let KeyCode::Char(c @ '1'..='9') = key_event.code {
+                    self.step(c as usize - '1' as usize);
+                    return false;
+                }
+
+                match Keymap::active().action(&key_event, &Self::ACTIONS) {
+                    Some(Action::PrevMove) => self.back(),
+                    Some(Action::NextMove) => self.step(0),
+                    Some(Action::Rotate) => self.rotated = !self.rotated,
+                    Some(Action::Frame) => self.frame = !self.frame,
+                    Some(Action::Help) => self.help = true,
+                    Some(Action::Suspend) => widgets::suspend(),
+                    Some(Action::Quit) => return true,
+                    _ => {}
+                }
+            }
+            Event::Mouse(mouse_event) => {
+                let moves = legal_moves(&self.root.board);
+                if let Some(mv) = self.select.mouse(&self.board_render(), mouse_event, &moves) {
+                    self.play(mv);
+                }
+            }
+            _ => {}
+        }
+
+        return false;
+    }
+
+    pub async fn mainloop(&mut self, engine: &mut EngineHandle) -> tokio::io::Result<()> {
+        let _terminal = widgets::TerminalGuard::new()?;
+
+        let mut event_stream = EventStream::new().fuse();
+        let (mut ingress, mut egress) = engine.split();
+
+        self.search();
+
+        loop {
+            self.render().await?;
+
+            select! {
+                ev = event_stream.next() => {
+                    if let Some(ev) = ev {
+                        if self.handle(ev?) {
+                            break;
+                        }
+                    }
+                }
+                msg = ingress.receive() => match msg {
+                    Err(e) if e.kind() == tokio::io::ErrorKind::InvalidData => {
+                        self.status = format!("Malformed output: {e}");
+                    }
+                    msg => self.receive(msg?),
+                },
+                _ = egress.send(self.outbox.front()), if !self.outbox.is_empty() => {
+                    self.outbox.pop_front();
+                }
+                _ = sleep(Duration::from_millis(50)) => {}
+            }
+        }
+
+        egress.send(Some(&UciGui::Stop())).await?;
+
+        Ok(())
+    }
+}
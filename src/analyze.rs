@@ -1,5 +1,5 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     path::PathBuf,
     process::{self, ExitCode},
     time::Duration,
@@ -7,14 +7,15 @@ use std::{
 
 use clap::Parser;
 use crossterm::{
-    event::{Event, EventStream, KeyCode, KeyModifiers},
+    cursor,
+    event::{Event, EventStream, KeyCode, KeyModifiers, MouseEventKind},
     queue,
     style::{self, ContentStyle, Stylize},
     terminal,
 };
 use mintymacks::{
     arrays::ArrayBoard,
-    bits::{board::BitBoard, two_bits},
+    bits::{bit, board::BitBoard, two_bits},
     eprintln_async,
     game::{FatMove, GameReview, GameState},
     model::{
@@ -33,25 +34,41 @@ use tokio_stream::StreamExt;
 
 use crate::{
     Runnable,
-    widgets::{self, TextRenderer, board::BoardRenderer},
+    keymap::{Action, Keymap},
+    uci_log::LogFile,
+    widgets::{
+        self, TextRenderer,
+        annotations::{AnnotationInput, Annotations, Arrow, PgnSource, Tint},
+        board::BoardRenderer,
+        material::Material,
+        move_input::MoveInput,
+        move_select::{MoveSelect, legal_moves},
+    },
 };
 
 #[derive(Parser)]
 pub struct ReviewGame {
     #[clap()]
     file: PathBuf,
+
+    /// UCI logs written by `fight` for the reviewed game, shown move by move
+    #[clap(long)]
+    log: Vec<PathBuf>,
 }
 
 impl Runnable for ReviewGame {
     async fn run(self) -> tokio::io::Result<()> {
-        let pgns = load_pgn_file(&String::from_utf8_lossy_owned(
-            tokio::fs::read(&self.file).await?,
-        ));
+        let text = String::from_utf8_lossy_owned(tokio::fs::read(&self.file).await?);
+        let sources = PgnSource::split(&text);
 
         let mut reviews = vec![];
 
-        for (ix, pgn) in pgns.into_iter().enumerate() {
+        for (ix, source) in sources.iter().enumerate() {
             let ix = ix + 1;
+            let Some(pgn) = load_pgn_file(&source.text).into_iter().next() else {
+                eprintln_async!("Error in parsing PGN game #{}", ix).await;
+                ExitCode::FAILURE.exit_process();
+            };
             let game = GameState::from_pgn(&pgn);
 
             let game = match game {
@@ -70,12 +87,41 @@ impl Runnable for ReviewGame {
             ExitCode::FAILURE.exit_process();
         }
 
+        let annotations = sources
+            .iter()
+            .map(|source| {
+                (0..source.anchors.len())
+                    .map(|ply| (ply, Annotations::parse(&source.comment(ply))))
+                    .filter(|(_, a)| !a.is_empty())
+                    .collect::<HashMap<_, _>>()
+            })
+            .collect();
+
+        let material = reviews.iter_mut().map(material_by_ply).collect();
+
+        let mut logs = vec![];
+        for path in &self.log {
+            logs.push(LogFile::load(path).await?);
+        }
+
         let mut gr = GameReviewer {
             file: self.file.clone(),
             reviews,
             index: 0,
             rotated: false,
+            frame: false,
             offset: 0,
+            select: MoveSelect::default(),
+            input: MoveInput::default(),
+            status: String::new(),
+            sources,
+            annotations,
+            material,
+            drawing: AnnotationInput::default(),
+            help: false,
+            show_log: !logs.is_empty(),
+            logs,
+            log_scroll: 0,
         };
 
         gr.mainloop().await?;
@@ -84,20 +130,81 @@ impl Runnable for ReviewGame {
     }
 }
 
+/// The material at every ply of a game, with captures counted from its
+/// moves. Leaves the review at its start.
+pub fn material_by_ply(review: &mut GameReview) -> Vec<Material> {
+    review.to_start();
+    let mut res = vec![Material::count(&review.cursor.render())];
+    loop {
+        let before = review.past.len();
+        review.next();
+        let Some(fm) = review.past.back().filter(|_| review.past.len() > before) else {
+            break;
+        };
+        let next = res
+            .last()
+            .unwrap()
+            .after(fm.chessmove.cpc.color(), &review.cursor.render());
+        res.push(next);
+    }
+    review.to_start();
+    res
+}
+
 pub struct GameReviewer {
     pub file: PathBuf,
     pub reviews: Vec<GameReview>,
     pub index: usize,
     pub rotated: bool,
+    pub frame: bool,
     pub offset: usize,
+    pub select: MoveSelect,
+    pub input: MoveInput,
+    pub status: String,
+    /// The games as written in the file
+    pub sources: Vec<PgnSource>,
+    /// Board annotations by game and ply
+    pub annotations: Vec<HashMap<usize, Annotations>>,
+    /// Material and captures by game and ply
+    pub material: Vec<Vec<Material>>,
+    pub drawing: AnnotationInput,
+    pub help: bool,
+    pub logs: Vec<LogFile>,
+    /// Show the logs in place of the tags and material
+    pub show_log: bool,
+    /// Log lines scrolled back from the end of the current section
+    pub log_scroll: usize,
 }
 
 impl GameReviewer {
+    pub const ACTIONS: [Action; 16] = {
+        use Action::*;
+        [
+            PrevMove,
+            NextMove,
+            FirstMove,
+            LastMove,
+            PrevGame,
+            NextGame,
+            Rotate,
+            Frame,
+            ClearAnnotations,
+            WriteAnnotated,
+            Log,
+            LogBack,
+            LogForward,
+            Help,
+            Suspend,
+            Quit,
+        ]
+    };
+
     pub fn board_render(&self) -> BoardRenderer {
         BoardRenderer {
             col: 3,
             row: 2,
             rotated: self.rotated,
+            frame: self.frame,
         }
     }
 
@@ -140,13 +247,141 @@ impl GameReviewer {
     }
 
     pub fn reminder_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 2 + 8 * 3 + 2,
+            col: 3,
+            style: ContentStyle::new().with(Self::GREY),
+        }
+    }
+
+    pub fn material_renderer(&self) -> TextRenderer {
+        TextRenderer {
+            col: 3 + 5 * 8 + 1 + 15 + 1,
+            row: 2 + 14,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn log_renderer(&self) -> TextRenderer {
+        TextRenderer {
+            col: 3 + 5 * 8 + 1 + 15 + 1,
+            row: 2,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub const LOG_ROWS: usize = 8 * 3;
+    pub const LOG_WIDTH: usize = 64;
+
+    /// Each log's section for the search that led to the current position,
+    /// under the log's file name. Shows the end of the sections unless
+    /// scrolled back.
+    pub fn log_text(&self) -> String {
+        let rows = (Self::LOG_ROWS / self.logs.len().max(1)).saturating_sub(1);
+
+        let mut res = vec![];
+        for log in &self.logs {
+            let section = log.section(self.ply());
+            let end = section
+                .len()
+                .saturating_sub(self.log_scroll)
+                .max(rows.min(section.len()));
+            let start = end.saturating_sub(rows);
+
+            if end < section.len() {
+                res.push(format!(
+                    "{}: {}-{} of {}",
+                    log.path.to_string_lossy(),
+                    start + 1,
+                    end,
+                    section.len()
+                ));
+            } else {
+                res.push(format!("{}:", log.path.to_string_lossy()));
+            }
+            res.extend(
+                section[start..end]
+                    .iter()
+                    .map(|e| e.to_string().chars().take(Self::LOG_WIDTH).collect()),
+            );
+        }
+
+        res.join("\n")
+    }
+
+    /// Scrolls the log pane by half its height, back or forward, no further
+    /// than the start of the longest section.
+    pub fn scroll_log(&mut self, back: bool) {
+        let step = Self::LOG_ROWS / self.logs.len().max(1) / 2;
+        let longest = self
+            .logs
+            .iter()
+            .map(|log| log.section(self.ply()).len())
+            .max()
+            .unwrap_or(0);
+
+        self.log_scroll = if back {
+            (self.log_scroll + step).min(longest)
+        } else {
+            self.log_scroll.saturating_sub(step)
+        };
+    }
+
+    pub fn input_renderer() -> TextRenderer {
         TextRenderer {
             row: 2 + 8 * 3 + 1,
             col: 3,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn status_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 2 + 8 * 3 + 1,
+            col: 3 + 5 * 8 + 1,
             style: ContentStyle::new().with(Self::GREY),
         }
     }
 
+    pub fn help_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 3,
+            col: 5,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn help_text() -> String {
+        format!(
+            "{}\n\n{:<24}{}\n{:<24}{}\n{:<24}{}\n{:<24}{}",
+            Keymap::active().help(&Self::ACTIONS),
+            "[Click] or drag",
+            "guess the next move",
+            "SAN and [Enter]",
+            "guess the next move",
+            "[Right-click] or drag",
+            "mark square or draw arrow",
+            "SAN and [Alt+Enter]",
+            "draw arrow",
+        )
+    }
+
+    pub fn go_first_move(&mut self) {
+        self.select.reset();
+        self.current_mut().to_start();
+    }
+
+    pub fn go_last_move(&mut self) {
+        self.select.reset();
+        loop {
+            let before = self.ply();
+            self.current_mut().next();
+            if self.ply() == before {
+                break;
+            }
+        }
+    }
+
     pub fn current(&self) -> &GameReview {
         &self.reviews[self.index]
     }
@@ -160,6 +395,7 @@ impl GameReviewer {
             self.current_mut().to_start();
             self.index -= 1;
             self.offset = 0;
+            self.select.reset();
         }
     }
 
@@ -168,11 +404,57 @@ impl GameReviewer {
             self.current_mut().to_start();
             self.index += 1;
             self.offset = 0;
+            self.select.reset();
+        }
+    }
+
+    pub fn ply(&self) -> usize {
+        self.current().past.len()
+    }
+
+    pub fn annotations_mut(&mut self) -> &mut Annotations {
+        let ply = self.ply();
+        self.annotations[self.index].entry(ply).or_default()
+    }
+
+    /// Writes all games with their annotations next to the reviewed file,
+    /// keeping the rest of the movetext as it was.
+    pub async fn write_annotated(&self) -> tokio::io::Result<PathBuf> {
+        let mut out = String::new();
+
+        for (source, annotations) in self.sources.iter().zip(&self.annotations) {
+            out += &source.annotate(annotations);
+            out += "\n\n";
+        }
+
+        let path = self.file.with_extension("annotated.pgn");
+        tokio::fs::write(&path, out).await?;
+        Ok(path)
+    }
+
+    /// Plays a move entered on the board, which only advances the review
+    /// if it is the move that was played in the game.
+    pub fn play(&mut self, mv: ChessMove) {
+        let before = self.current().past.len();
+        self.current_mut().next();
+
+        if self.current().past.len() == before {
+            self.status = String::from("End of game");
+        } else if self.current().past.back().map(|fm| fm.chessmove) == Some(mv) {
+            self.status.clear();
+        } else {
+            self.current_mut().prev();
+            self.status = String::from("Not the game move");
         }
     }
 
     pub async fn render(&self) -> tokio::io::Result<()> {
-        let board = self.current().cursor.render();
+        let mut board = self.current().cursor.render();
+        let moves = legal_moves(&self.current().cursor);
+        let mut selectable = self.select.selectable(&moves);
+        if let Some(picker) = self.select.show_promotion(&mut board) {
+            selectable = picker;
+        }
         let highlight = match self.current().past.back() {
             None => 0,
             Some(fm) => {
@@ -204,7 +486,17 @@ impl GameReviewer {
 
         queue!(res, terminal::Clear(terminal::ClearType::Purge));
 
-        res.append(&mut self.board_render().render(&board, highlight, 0));
+        let empty = Annotations::default();
+        res.append(
+            &mut self.board_render().render_annotated(
+                &board,
+                highlight | bit(self.select.origin),
+                selectable,
+                self.annotations[self.index]
+                    .get(&self.ply())
+                    .unwrap_or(&empty),
+            ),
+        );
 
         res.append(&mut self.title_renderer().render(&format!(
             "Game {} of {} in file {}",
@@ -245,54 +537,174 @@ impl GameReviewer {
                 .render(&format!("{}", future_moves.join("\n"))),
         );
 
-        res.append(&mut self.metadata_renderer().render(&format!("{}", {
-            let mut x = String::new();
-            self.current().tags.to_string(&mut x);
-            x
-        })));
+        if self.show_log {
+            // nothing lies right of the pane, and scrolled lines differ in
+            // length
+            let pane = self.log_renderer();
+            for row in 0..Self::LOG_ROWS as u16 {
+                queue!(
+                    res,
+                    cursor::MoveTo(pane.col, pane.row + row),
+                    terminal::Clear(terminal::ClearType::UntilNewLine)
+                );
+            }
+            res.append(&mut pane.render(&self.log_text()));
+        } else {
+            res.append(&mut self.metadata_renderer().render(&format!("{}", {
+                let mut x = String::new();
+                self.current().tags.to_string(&mut x);
+                x
+            })));
+
+            let material = match self.material[self.index].get(self.ply()) {
+                Some(material) => material.clone(),
+                None => Material::count(&self.current().cursor.render()),
+            };
+            res.append(&mut self.material_renderer().render(&material.render()));
+        }
 
         res.append(
-            &mut Self::reminder_renderer()
-                .render("[↑] and [↓]: navigate moves\n[x]: rotate board\n[Ctrl]+[←] and [Ctrl]+[→]: navigate between games\n[Ctrl]+[C] or [ESC]: Exit"),
+            &mut Self::input_renderer().render(
+                &self
+                    .input
+                    .render(&MoveInput::trie(&self.current().cursor, &moves)),
+            ),
         );
 
+        res.append(&mut Self::status_renderer().render(&self.status));
+
+        let keymap = Keymap::active();
+        res.append(&mut Self::reminder_renderer().render(&format!(
+            "{}: help\n{}: exit",
+            keymap.describe(Action::Help),
+            keymap.describe(Action::Quit),
+        )));
+
+        if self.help {
+            res.append(&mut Self::help_renderer().render_boxed("Keys", &Self::help_text()));
+        }
+
         stdout().write_all(&res[..]).await?;
 
         Ok(())
     }
 
-    pub fn handle(&mut self, ev: Event) -> bool {
+    pub async fn handle(&mut self, ev: Event) -> bool {
+        let position = (self.index, self.ply());
+
         match ev {
+            Event::Key(key_event)
+                if key_event.is_press()
+                    && key_event.code == KeyCode::Enter
+                    && key_event.modifiers.contains(KeyModifiers::ALT)
+                    && !self.input.is_empty() =>
+            {
+                let moves = legal_moves(&self.current().cursor);
+                let trie = MoveInput::trie(&self.current().cursor, &moves);
+                if let Some(mv) = self.input.resolve(&trie) {
+                    self.input.buffer.clear();
+                    self.annotations_mut()
+                        .toggle_arrow(Arrow::from_move(mv, Tint::default()));
+                }
+            }
+            Event::Key(key_event)
+                if (key_event.is_press() || key_event.is_repeat())
+                    && self.input.accepts(&key_event)
+                    && !(self.input.is_empty()
+                        && Keymap::active()
+                            .action(&key_event, &Self::ACTIONS)
+                            .is_some()) =>
+            {
+                let moves = legal_moves(&self.current().cursor);
+                let trie = MoveInput::trie(&self.current().cursor, &moves);
+                if let Some(mv) = self.input.handle(key_event, &trie) {
+                    self.play(mv);
+                }
+            }
             Event::Key(key_event) if key_event.is_press() || key_event.is_repeat() => {
-                match key_event.code {
-                    KeyCode::Left if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                        self.go_prev_game();
-                    }
-                    KeyCode::Right if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                        self.go_next_game();
-                    }
-                    KeyCode::Up => {
+                let action = Keymap::active().action(&key_event, &Self::ACTIONS);
+
+                if self.help {
+                    self.help = false;
+                    return false;
+                }
+
+                match action {
+                    Some(Action::PrevGame) => self.go_prev_game(),
+                    Some(Action::NextGame) => self.go_next_game(),
+                    Some(Action::PrevMove) => {
+                        self.select.reset();
                         self.current_mut().prev();
                     }
-                    KeyCode::Down => {
+                    Some(Action::NextMove) => {
+                        self.select.reset();
                         self.current_mut().next();
                     }
-                    KeyCode::Char('x') => self.rotated = !self.rotated,
-                    KeyCode::Esc => return true,
-                    KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                        return true;
+                    Some(Action::FirstMove) => self.go_first_move(),
+                    Some(Action::LastMove) => self.go_last_move(),
+                    Some(Action::Rotate) => self.rotated = !self.rotated,
+                    Some(Action::Frame) => self.frame = !self.frame,
+                    Some(Action::ClearAnnotations) => self.annotations_mut().clear(),
+                    Some(Action::WriteAnnotated) => {
+                        self.status = match self.write_annotated().await {
+                            Ok(path) => format!("Written to {}", path.to_string_lossy()),
+                            Err(e) => format!("Unable to write: {e}"),
+                        };
+                    }
+                    Some(Action::Log) if self.logs.is_empty() => {
+                        self.status = String::from("No logs given");
                     }
+                    Some(Action::LogBack) if self.show_log => self.scroll_log(true),
+                    Some(Action::LogForward) if self.show_log => self.scroll_log(false),
+                    Some(Action::Log) => {
+                        self.show_log = !self.show_log;
+                        // the panes differ in size
+                        let _ = crossterm::execute!(
+                            std::io::stdout(),
+                            terminal::Clear(terminal::ClearType::All)
+                        );
+                    }
+                    Some(Action::Help) => self.help = true,
+                    Some(Action::Suspend) => widgets::suspend(),
+                    Some(Action::Quit) => return true,
                     _ => {}
                 }
             }
+            Event::Mouse(mouse_event)
+                if self.show_log
+                    && matches!(
+                        mouse_event.kind,
+                        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown
+                    ) =>
+            {
+                self.scroll_log(mouse_event.kind == MouseEventKind::ScrollUp);
+            }
+            Event::Mouse(mouse_event) => {
+                let renderer = self.board_render();
+                let ply = self.ply();
+                let annotations = self.annotations[self.index].entry(ply).or_default();
+                if self.drawing.mouse(&renderer, mouse_event, annotations) {
+                    return false;
+                }
+
+                let moves = legal_moves(&self.current().cursor);
+                if let Some(mv) = self.select.mouse(&renderer, mouse_event, &moves) {
+                    self.play(mv);
+                }
+            }
             _ => {}
         }
 
+        // a new section starts at its end
+        if (self.index, self.ply()) != position {
+            self.log_scroll = 0;
+        }
+
         return false;
     }
 
     pub async fn mainloop(&mut self) -> tokio::io::Result<()> {
-        widgets::setup().await?;
+        let _terminal = widgets::TerminalGuard::new()?;
 
         let mut event_stream = EventStream::new().fuse();
 
@@ -304,7 +716,7 @@ impl GameReviewer {
             select! {
                 ev = event => {
                     if let Some(ev) = ev {
-                        if self.handle(ev?) {
+                        if self.handle(ev?).await {
                             break;
                         }
                     }
@@ -313,8 +725,6 @@ impl GameReviewer {
             }
         }
 
-        widgets::teardown().await?;
-
         Ok(())
     }
 }
@@ -0,0 +1,734 @@
+use std::{collections::VecDeque, fmt::Display, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::Parser;
+use mintymacks::{
+    deque,
+    engine::EngineHandle,
+    game::GameState,
+    notation::{
+        LongAlg,
+        uci::{
+            engine::UciEngine,
+            gui::{GoCommand, UciGui},
+        },
+    },
+    utils::{eprintln_async, println_async},
+};
+use tokio::time::{Instant, timeout, timeout_at};
+
+use crate::{
+    Runnable,
+    analysis::play_lan,
+    faceoff::{option_value, read_profile},
+    widgets::move_select::game_from_fen,
+};
+
+#[derive(Parser)]
+pub struct CheckEngine {
+    /// Bot profile
+    pub profile: PathBuf,
+}
+
+impl Runnable for CheckEngine {
+    async fn run(self) -> tokio::io::Result<()> {
+        let profile = read_profile(&self.profile).await?;
+        eprintln_async!("Checking {}...", profile.engine.name).await;
+
+        let mut probe = match Probe::spawn(&profile.engine.command).await {
+            Ok(probe) => probe,
+            Err(e) => {
+                eprintln_async!("Unable to start engine: {e}").await;
+                ExitCode::FAILURE.exit_process();
+            }
+        };
+        probe.settings = profile
+            .options
+            .iter()
+            .map(|(name, value)| (name.clone(), option_value(value)))
+            .collect();
+
+        let checks = probe.run_all().await?;
+
+        let mut failures = 0;
+        for check in &checks {
+            println_async!("{check}").await;
+            if check.outcome == Outcome::Fail {
+                failures += 1;
+            }
+        }
+        println_async!("{} checks, {} failed", checks.len(), failures).await;
+
+        if failures > 0 {
+            ExitCode::FAILURE.exit_process();
+        }
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The engine does not claim the feature
+    Skip,
+}
+
+/// A scenario and how the engine did in it.
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: impl Into<String>, passed: bool, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            outcome: if passed { Outcome::Pass } else { Outcome::Fail },
+            detail: detail.into(),
+        }
+    }
+
+    pub fn skip(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            outcome: Outcome::Skip,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = match self.outcome {
+            Outcome::Pass => "PASS",
+            Outcome::Fail => "FAIL",
+            Outcome::Skip => "SKIP",
+        };
+        write!(f, "{outcome}  {}", self.name)?;
+        if !self.detail.is_empty() {
+            write!(f, ": {}", self.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Messages an engine sent in answer to some commands.
+pub struct Exchange<T = UciEngine> {
+    pub messages: Vec<T>,
+    /// Whether the awaited message arrived in time
+    pub done: bool,
+    pub elapsed: Duration,
+}
+
+impl<T> Exchange<T> {
+    pub fn last(&self) -> Option<&T> {
+        self.messages.last()
+    }
+
+    pub fn millis(&self) -> String {
+        format!("{} ms", self.elapsed.as_millis())
+    }
+}
+
+/// An option as announced by the engine, e.g.
+/// `option name Hash type spin default 16 min 1 max 33554432`.
+pub struct AnnouncedOption {
+    pub name: String,
+    pub kind: String,
+    pub default: Option<String>,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub vars: Vec<String>,
+}
+
+impl AnnouncedOption {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("option") {
+            return None;
+        }
+
+        // values run until the next keyword, names and strings may have spaces
+        let mut fields: Vec<(&str, Vec<&str>)> = vec![];
+        for token in tokens {
+            match token {
+                "name" | "type" | "default" | "min" | "max" | "var" => fields.push((token, vec![])),
+                _ => fields.last_mut()?.1.push(token),
+            }
+        }
+
+        let mut res = AnnouncedOption {
+            name: String::new(),
+            kind: String::new(),
+            default: None,
+            min: None,
+            max: None,
+            vars: vec![],
+        };
+        for (key, value) in fields {
+            let value = value.join(" ");
+            match key {
+                "name" => res.name = value,
+                "type" => res.kind = value,
+                "default" => res.default = Some(value),
+                "min" => res.min = Some(value),
+                "max" => res.max = Some(value),
+                _ => res.vars.push(value),
+            }
+        }
+
+        (!res.name.is_empty()).then_some(res)
+    }
+
+    /// Values to set, ending with the default. Spins are not set to their
+    /// maximum, which for `Hash` can exhaust memory.
+    pub fn test_values(&self) -> Vec<Option<String>> {
+        let default = self.default.clone().filter(|d| d != "<empty>");
+        match &self.kind[..] {
+            "button" => vec![None],
+            "check" => {
+                let flipped = if default.as_deref() == Some("true") {
+                    "false"
+                } else {
+                    "true"
+                };
+                vec![Some(flipped.to_string()), default]
+            }
+            "spin" => vec![self.min.clone(), default],
+            "combo" => {
+                let mut res = self.vars.iter().cloned().map(Some).collect::<Vec<_>>();
+                res.push(default);
+                res
+            }
+            _ => vec![default],
+        }
+    }
+}
+
+/// The test position after the given moves.
+pub fn test_position(fen: &str, moves: &[&str]) -> Result<GameState, String> {
+    let mut game = game_from_fen(fen)?;
+    for mv in moves {
+        play_lan(&mut game, mv).ok_or_else(|| format!("Illegal move {mv} at {fen}"))?;
+    }
+    Ok(game)
+}
+
+/// Checks the message ending a search against the position it was asked
+/// for.
+pub fn legal_bestmove(game: &GameState, msg: Option<&UciEngine>) -> Result<String, String> {
+    let bm = match msg {
+        Some(UciEngine::BestMove(bm)) => bm,
+        Some(other) => return Err(format!("expected `bestmove', got `{other}'")),
+        None => return Err(String::from("expected `bestmove', got nothing")),
+    };
+
+    let best = bm.best.0.longalg(bm.best.1);
+    match game.find_move(bm.best.clone()) {
+        Ok(_) => Ok(best),
+        Err(_) => Err(format!("illegal move {best}")),
+    }
+}
+
+fn is_bestmove(msg: &UciEngine) -> bool {
+    matches!(msg, UciEngine::BestMove(..))
+}
+
+/// Drives an engine through scenarios, keeping everything it sends so that
+/// the checks see exactly what it answered.
+pub struct Probe {
+    pub engine: EngineHandle,
+    pub options: Vec<AnnouncedOption>,
+    /// The options of the profile, set before the searches as `fight` sets
+    /// them
+    pub settings: Vec<(String, String)>,
+}
+
+impl Probe {
+    pub const HANDSHAKE: Duration = Duration::from_millis(2000);
+    pub const READY: Duration = Duration::from_millis(1000);
+    pub const SEARCH: Duration = Duration::from_millis(10_000);
+    /// Slack allowed on `movetime' and after `stop'
+    pub const LATENCY: Duration = Duration::from_millis(500);
+
+    pub const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    pub const MOVES: [&str; 3] = ["f1c4", "g8f6", "e1g1"];
+    /// A Chess960 start with the king between its rooks, castled by taking
+    /// the rook
+    pub const FEN_960: &str = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN w GEge - 0 1";
+    pub const MOVES_960: [&str; 2] = ["f1g1", "f8g8"];
+
+    pub async fn spawn(command: &(PathBuf, Vec<String>)) -> tokio::io::Result<Self> {
+        Ok(Probe {
+            engine: EngineHandle::open(&command.0, &command.1, false).await?,
+            options: vec![],
+            settings: vec![],
+        })
+    }
+
+    /// Sends the commands, then reads until a message satisfies `done` or
+    /// the time runs out.
+    pub async fn interleave_until(
+        &mut self,
+        mut commands: VecDeque<UciGui>,
+        done: impl Fn(&UciEngine) -> bool,
+        limit: Duration,
+    ) -> tokio::io::Result<Exchange> {
+        let started = Instant::now();
+        let mut messages = vec![];
+        self.engine
+            .interleave_until(&mut commands, &mut messages, &done, limit)
+            .await?;
+
+        Ok(Exchange {
+            done: messages.last().is_some_and(&done),
+            messages,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Like `interleave_until`, but keeps every line as text, including
+    /// those that are not UCI, such as a `go perft` divide.
+    pub async fn interleave_lines(
+        &mut self,
+        commands: VecDeque<UciGui>,
+        done: impl Fn(&str) -> bool,
+        limit: Duration,
+    ) -> tokio::io::Result<Exchange<String>> {
+        let started = Instant::now();
+        let deadline = started + limit;
+        let (mut ingress, mut egress) = self.engine.split();
+        for cmd in &commands {
+            egress.send(Some(cmd)).await?;
+        }
+
+        let mut messages = vec![];
+        loop {
+            let line = match timeout_at(deadline, ingress.receive()).await {
+                Ok(Ok(msg)) => msg.to_string(),
+                Ok(Err(e)) if e.kind() == tokio::io::ErrorKind::InvalidData => e.to_string(),
+                // closed pipes and timeouts both end the exchange unfinished
+                Ok(Err(_)) | Err(_) => {
+                    return Ok(Exchange {
+                        messages,
+                        done: false,
+                        elapsed: started.elapsed(),
+                    });
+                }
+            };
+
+            let finished = done(&line);
+            messages.push(line);
+            if finished {
+                return Ok(Exchange {
+                    messages,
+                    done: true,
+                    elapsed: started.elapsed(),
+                });
+            }
+        }
+    }
+
+    pub async fn ready(&mut self) -> tokio::io::Result<Exchange> {
+        self.interleave_until(
+            deque![UciGui::IsReady()],
+            |x| x == &UciEngine::ReadyOk(),
+            Self::READY,
+        )
+        .await
+    }
+
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|o| o.name == name)
+    }
+
+    pub async fn run_all(&mut self) -> tokio::io::Result<Vec<Check>> {
+        let mut res = vec![];
+
+        let handshake = self.handshake().await?;
+        let alive = handshake.outcome == Outcome::Pass;
+        res.push(handshake);
+        if !alive {
+            return Ok(res);
+        }
+
+        let ready = self.ready().await?;
+        res.push(Check::new("isready", ready.done, ready.millis()));
+
+        res.append(&mut self.set_options().await?);
+        res.push(self.profile_options().await?);
+
+        let exchange = self
+            .interleave_until(
+                deque![UciGui::UciNewGame(), UciGui::IsReady()],
+                |x| x == &UciEngine::ReadyOk(),
+                Self::READY,
+            )
+            .await?;
+        res.push(Check::new("ucinewgame", exchange.done, exchange.millis()));
+
+        for (name, go) in [
+            ("go depth", GoCommand::Depth(5)),
+            ("go nodes", GoCommand::Nodes(10_000)),
+            (
+                "go wtime",
+                GoCommand::Clock {
+                    wtime: 10_000,
+                    btime: 10_000,
+                    winc: 100,
+                    binc: 100,
+                },
+            ),
+        ] {
+            res.push(self.search(name, go, None).await?);
+        }
+        res.push(
+            self.search(
+                "go movetime",
+                GoCommand::MoveTime(500),
+                Some(Duration::from_millis(500)),
+            )
+            .await?,
+        );
+
+        res.push(self.stop().await?);
+        res.push(self.chess960().await?);
+        res.push(self.quit().await?);
+
+        Ok(res)
+    }
+
+    pub async fn handshake(&mut self) -> tokio::io::Result<Check> {
+        let exchange = self
+            .interleave_until(
+                deque![UciGui::Uci()],
+                |x| x == &UciEngine::UciOk(),
+                Self::HANDSHAKE,
+            )
+            .await?;
+
+        let lines = exchange
+            .messages
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        self.options = lines
+            .iter()
+            .filter_map(|l| AnnouncedOption::parse(l))
+            .collect();
+
+        let named = lines.iter().any(|l| l.starts_with("id name"));
+        Ok(match (exchange.done, named) {
+            (false, _) => Check::new(
+                "uci",
+                false,
+                format!("no `uciok' within {}", exchange.millis()),
+            ),
+            (true, false) => Check::new("uci", false, "no `id name'"),
+            (true, true) => Check::new(
+                "uci",
+                true,
+                format!("{}, {} options", exchange.millis(), self.options.len()),
+            ),
+        })
+    }
+
+    /// Sets every announced option to a few valid values, checking that the
+    /// engine stays responsive.
+    pub async fn set_options(&mut self) -> tokio::io::Result<Vec<Check>> {
+        let mut res = vec![];
+
+        let options = std::mem::take(&mut self.options);
+        for option in &options {
+            let mut commands = option
+                .test_values()
+                .into_iter()
+                .map(|value| UciGui::SetOption(option.name.clone(), value))
+                .collect::<VecDeque<_>>();
+            commands.push_back(UciGui::IsReady());
+
+            let exchange = self
+                .interleave_until(commands, |x| x == &UciEngine::ReadyOk(), Self::READY)
+                .await?;
+            res.push(Check::new(
+                format!("setoption {} ({})", option.name, option.kind),
+                exchange.done,
+                if exchange.done {
+                    exchange.millis()
+                } else {
+                    String::from("no `readyok' afterwards")
+                },
+            ));
+        }
+        self.options = options;
+
+        Ok(res)
+    }
+
+    /// Sets the options of the profile, which must all be announced.
+    pub async fn profile_options(&mut self) -> tokio::io::Result<Check> {
+        let name = "profile options";
+        if self.settings.is_empty() {
+            return Ok(Check::skip(name, "none in the profile"));
+        }
+
+        let unknown = self
+            .settings
+            .iter()
+            .filter(|(option, _)| {
+                !self
+                    .options
+                    .iter()
+                    .any(|o| o.name.eq_ignore_ascii_case(option))
+            })
+            .map(|(option, _)| option.clone())
+            .collect::<Vec<_>>();
+
+        let mut commands = self
+            .settings
+            .iter()
+            .map(|(option, value)| UciGui::SetOption(option.clone(), Some(value.clone())))
+            .collect::<VecDeque<_>>();
+        commands.push_back(UciGui::IsReady());
+        let exchange = self
+            .interleave_until(commands, |x| x == &UciEngine::ReadyOk(), Self::READY)
+            .await?;
+
+        Ok(if !unknown.is_empty() {
+            Check::new(
+                name,
+                false,
+                format!("not announced: {}", unknown.join(", ")),
+            )
+        } else if !exchange.done {
+            Check::new(name, false, "no `readyok' afterwards")
+        } else {
+            Check::new(
+                name,
+                true,
+                format!("{} set, {}", self.settings.len(), exchange.millis()),
+            )
+        })
+    }
+
+    pub fn position(game: &GameState) -> UciGui {
+        UciGui::Position(game.uci_position(), game.uci_line())
+    }
+
+    /// Runs a search from the test position, which must end in a legal move
+    /// and, with a `movetime`, in time.
+    pub async fn search(
+        &mut self,
+        name: &str,
+        go: GoCommand,
+        movetime: Option<Duration>,
+    ) -> tokio::io::Result<Check> {
+        let game = match test_position(Self::FEN, &Self::MOVES) {
+            Ok(game) => game,
+            Err(e) => return Ok(Check::new(name, false, format!("test position: {e}"))),
+        };
+
+        // leftovers of earlier scenarios end before `readyok'
+        self.ready().await?;
+
+        let exchange = self
+            .interleave_until(
+                deque![Self::position(&game), UciGui::Go(go)],
+                is_bestmove,
+                Self::SEARCH,
+            )
+            .await?;
+
+        if !exchange.done {
+            return Ok(Check::new(
+                name,
+                false,
+                format!("no `bestmove' within {}", exchange.millis()),
+            ));
+        }
+
+        if let Err(e) = legal_bestmove(&game, exchange.last()) {
+            return Ok(Check::new(name, false, e));
+        }
+
+        Ok(match movetime {
+            Some(t) if exchange.elapsed > t + Self::LATENCY => {
+                Check::new(name, false, format!("took {}", exchange.millis()))
+            }
+            _ => Check::new(name, true, exchange.millis()),
+        })
+    }
+
+    pub async fn stop(&mut self) -> tokio::io::Result<Check> {
+        let game = match test_position(Self::FEN, &Self::MOVES) {
+            Ok(game) => game,
+            Err(e) => return Ok(Check::new("stop", false, format!("test position: {e}"))),
+        };
+
+        self.ready().await?;
+
+        // anything but a `bestmove' while searching is fine
+        let searching = self
+            .interleave_until(
+                deque![Self::position(&game), UciGui::Go(GoCommand::Infinite())],
+                is_bestmove,
+                Self::LATENCY,
+            )
+            .await?;
+        if searching.done {
+            return Ok(Check::new("stop", false, "`go infinite' ended on its own"));
+        }
+
+        let exchange = self
+            .interleave_until(deque![UciGui::Stop()], is_bestmove, Self::READY)
+            .await?;
+
+        if !exchange.done {
+            return Ok(Check::new(
+                "stop",
+                false,
+                format!("no `bestmove' within {}", exchange.millis()),
+            ));
+        }
+        if let Err(e) = legal_bestmove(&game, exchange.last()) {
+            return Ok(Check::new("stop", false, e));
+        }
+
+        Ok(Check::new(
+            "stop",
+            exchange.elapsed <= Self::LATENCY,
+            format!("`bestmove' after {}", exchange.millis()),
+        ))
+    }
+
+    /// Castling in Chess960 is written as the king taking its rook.
+    pub async fn chess960(&mut self) -> tokio::io::Result<Check> {
+        if !self.has_option("UCI_Chess960") {
+            return Ok(Check::skip("chess960", "no UCI_Chess960 option"));
+        }
+        let game = match test_position(Self::FEN_960, &Self::MOVES_960) {
+            Ok(game) => game,
+            Err(e) => return Ok(Check::new("chess960", false, format!("test position: {e}"))),
+        };
+        self.ready().await?;
+
+        let exchange = self
+            .interleave_until(
+                deque![
+                    UciGui::SetOption(String::from("UCI_Chess960"), Some(String::from("true"))),
+                    UciGui::UciNewGame(),
+                    Self::position(&game),
+                    UciGui::Go(GoCommand::Depth(5))
+                ],
+                is_bestmove,
+                Self::SEARCH,
+            )
+            .await?;
+
+        let res = if !exchange.done {
+            Check::new(
+                "chess960",
+                false,
+                format!("no `bestmove' within {}", exchange.millis()),
+            )
+        } else {
+            match legal_bestmove(&game, exchange.last()) {
+                Ok(_) => Check::new("chess960", true, exchange.millis()),
+                Err(e) => Check::new("chess960", false, e),
+            }
+        };
+
+        self.interleave_until(
+            deque![
+                UciGui::SetOption(String::from("UCI_Chess960"), Some(String::from("false"))),
+                UciGui::IsReady()
+            ],
+            |x| x == &UciEngine::ReadyOk(),
+            Self::READY,
+        )
+        .await?;
+
+        Ok(res)
+    }
+
+    pub async fn quit(&mut self) -> tokio::io::Result<Check> {
+        let started = Instant::now();
+
+        Ok(match timeout(Self::READY, self.engine.quit()).await {
+            Ok(Ok(_)) => Check::new(
+                "quit",
+                true,
+                format!("{} ms", started.elapsed().as_millis()),
+            ),
+            Ok(Err(e)) => Check::new("quit", false, e.to_string()),
+            Err(_) => Check::new("quit", false, "still running"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_parse() {
+        test_position(Probe::FEN, &Probe::MOVES).unwrap();
+        test_position(Probe::FEN_960, &Probe::MOVES_960).unwrap();
+    }
+
+    #[test]
+    fn spin_option() {
+        let option =
+            AnnouncedOption::parse("option name Hash type spin default 16 min 1 max 33554432")
+                .unwrap();
+        assert_eq!(option.name, "Hash");
+        assert_eq!(option.kind, "spin");
+        assert_eq!(option.default.as_deref(), Some("16"));
+        assert_eq!(option.min.as_deref(), Some("1"));
+        assert_eq!(option.max.as_deref(), Some("33554432"));
+        assert_eq!(
+            option.test_values(),
+            [Some(String::from("1")), Some(String::from("16"))]
+        );
+    }
+
+    #[test]
+    fn names_and_values_with_spaces() {
+        let option = AnnouncedOption::parse(
+            "option name Skill Level type combo default Club Player var Beginner var Club Player",
+        )
+        .unwrap();
+        assert_eq!(option.name, "Skill Level");
+        assert_eq!(option.default.as_deref(), Some("Club Player"));
+        assert_eq!(option.vars, ["Beginner", "Club Player"]);
+    }
+
+    #[test]
+    fn buttons_and_empty_strings() {
+        let button = AnnouncedOption::parse("option name Clear Hash type button").unwrap();
+        assert_eq!(button.kind, "button");
+        assert_eq!(button.default, None);
+        assert_eq!(button.test_values(), [None]);
+
+        let string =
+            AnnouncedOption::parse("option name SyzygyPath type string default <empty>").unwrap();
+        assert_eq!(string.test_values(), [None]);
+    }
+
+    #[test]
+    fn check_is_flipped_and_restored() {
+        let option = AnnouncedOption::parse("option name Ponder type check default false").unwrap();
+        assert_eq!(
+            option.test_values(),
+            [Some(String::from("true")), Some(String::from("false"))]
+        );
+    }
+
+    #[test]
+    fn not_an_option() {
+        assert!(AnnouncedOption::parse("id name Stockfish").is_none());
+        assert!(AnnouncedOption::parse("option type spin").is_none());
+        assert!(AnnouncedOption::parse("option Hash").is_none());
+    }
+}
@@ -0,0 +1,520 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use crossterm::{
+    event::{Event, EventStream, KeyCode, KeyEvent},
+    queue,
+    style::{self, Attribute, ContentStyle, Stylize},
+    terminal,
+};
+use mintymacks::utils::eprintln_async;
+use tokio::io::{AsyncWriteExt, stdout};
+use tokio_stream::StreamExt;
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+use crate::{
+    Runnable,
+    check_engine::{AnnouncedOption, Outcome, Probe},
+    faceoff::{option_value, read_profile},
+    keymap::{Action, Keymap},
+    registry::{load_inherited, resolve_profile},
+    widgets::{self, TextRenderer},
+};
+
+#[derive(Parser)]
+pub struct EditProfile {
+    /// Bot profile
+    pub profile: PathBuf,
+}
+
+impl Runnable for EditProfile {
+    async fn run(self) -> tokio::io::Result<()> {
+        let file = resolve_profile(&self.profile).await?;
+        let text = String::from_utf8_lossy_owned(tokio::fs::read(&file).await?);
+        let doc = match text.parse::<DocumentMut>() {
+            Ok(doc) => doc,
+            Err(e) => {
+                eprintln_async!("Error in parsing profile: {e}").await;
+                ExitCode::FAILURE.exit_process();
+            }
+        };
+
+        let profile = read_profile(&file).await?;
+        let inherited = load_inherited(&file).await?;
+        eprintln_async!("Asking {} for its options...", profile.engine.name).await;
+        let mut probe = Probe::spawn(&profile.engine.command).await?;
+        let handshake = probe.handshake().await?;
+        probe.quit().await?;
+        if handshake.outcome != Outcome::Pass {
+            eprintln_async!("{handshake}").await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        let mut editor = ProfileEditor {
+            file,
+            options: std::mem::take(&mut probe.options),
+            doc,
+            inherited,
+            cursor: 0,
+            offset: 0,
+            buffer: None,
+            status: String::new(),
+            modified: false,
+            quitting: false,
+            preview: false,
+            help: false,
+        };
+
+        editor.mainloop().await?;
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+/// Edits the `[options]` table of a profile in place, so that comments and
+/// options the editor does not touch stay as they were.
+pub struct ProfileEditor {
+    pub file: PathBuf,
+    pub options: Vec<AnnouncedOption>,
+    pub doc: DocumentMut,
+    /// The profile it extends, merged as `fight` reads it
+    pub inherited: toml::Table,
+    pub cursor: usize,
+    /// First option shown
+    pub offset: usize,
+    /// Text being typed for a spin or string option
+    pub buffer: Option<String>,
+    pub status: String,
+    pub modified: bool,
+    /// Asked to exit once with unsaved changes
+    pub quitting: bool,
+    pub preview: bool,
+    pub help: bool,
+}
+
+impl ProfileEditor {
+    pub const ROWS: usize = 24;
+    pub const SLIDER: usize = 20;
+
+    pub const ACTIONS: [Action; 13] = {
+        use Action::*;
+        [
+            PrevMove,
+            NextMove,
+            Decrease,
+            Increase,
+            DecreaseMore,
+            IncreaseMore,
+            Activate,
+            RemoveOption,
+            Preview,
+            WriteProfile,
+            Help,
+            Suspend,
+            Quit,
+        ]
+    };
+
+    pub const GREY: style::Color = style::Color::Rgb {
+        r: 0x88,
+        g: 0x88,
+        b: 0x88,
+    };
+
+    pub fn title_renderer() -> TextRenderer {
+        TextRenderer {
+            col: 3,
+            row: 1,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn row_renderer(row: usize, selected: bool) -> TextRenderer {
+        let mut style = ContentStyle::new();
+        if selected {
+            style.attributes.set(Attribute::Reverse);
+        }
+        TextRenderer {
+            col: 3,
+            row: 2 + row as u16,
+            style,
+        }
+    }
+
+    pub fn status_renderer() -> TextRenderer {
+        TextRenderer {
+            col: 3,
+            row: 2 + Self::ROWS as u16 + 1,
+            style: ContentStyle::new().with(Self::GREY),
+        }
+    }
+
+    pub fn reminder_renderer() -> TextRenderer {
+        TextRenderer {
+            col: 3,
+            row: 2 + Self::ROWS as u16 + 2,
+            style: ContentStyle::new().with(Self::GREY),
+        }
+    }
+
+    pub fn help_renderer() -> TextRenderer {
+        TextRenderer {
+            row: 3,
+            col: 5,
+            style: ContentStyle::new(),
+        }
+    }
+
+    pub fn help_text() -> String {
+        Keymap::active().help(&Self::ACTIONS)
+    }
+
+    pub fn table(&self) -> Option<&Table> {
+        self.doc.get("options")?.as_table()
+    }
+
+    pub fn table_mut(&mut self) -> &mut Table {
+        let item = self.doc.entry("options").or_insert(toml_edit::table());
+        if !item.is_table() {
+            *item = toml_edit::table();
+        }
+        item.as_table_mut().unwrap()
+    }
+
+    pub fn current(&self) -> &AnnouncedOption {
+        &self.options[self.cursor]
+    }
+
+    /// The value set in the profile, as the engine would be sent it.
+    pub fn value(&self, name: &str) -> Option<String> {
+        match self.table()?.get(name)?.as_value()? {
+            Value::String(s) => Some(s.value().clone()),
+            Value::Integer(i) => Some(i.value().to_string()),
+            Value::Boolean(b) => Some(b.value().to_string()),
+            Value::Float(f) => Some(f.value().to_string()),
+            _ => None,
+        }
+    }
+
+    /// The value the profile inherits through `extends`, if it does not set
+    /// one itself.
+    pub fn inherited(&self, name: &str) -> Option<String> {
+        self.inherited.get("options")?.get(name).map(option_value)
+    }
+
+    /// Checks a value against the option's type before it goes in the
+    /// profile.
+    pub fn validate(option: &AnnouncedOption, value: &str) -> Result<Value, String> {
+        match &option.kind[..] {
+            "spin" => {
+                let n = value
+                    .parse::<i64>()
+                    .map_err(|_| format!("{} takes a number", option.name))?;
+                let min = option.min.as_deref().and_then(|m| m.parse().ok());
+                let max = option.max.as_deref().and_then(|m| m.parse().ok());
+                if min.is_some_and(|m: i64| n < m) || max.is_some_and(|m: i64| n > m) {
+                    return Err(format!(
+                        "{} must be between {} and {}",
+                        option.name,
+                        option.min.as_deref().unwrap_or("?"),
+                        option.max.as_deref().unwrap_or("?"),
+                    ));
+                }
+                Ok(Value::from(n))
+            }
+            "check" => match value {
+                "true" => Ok(Value::from(true)),
+                "false" => Ok(Value::from(false)),
+                _ => Err(format!("{} is true or false", option.name)),
+            },
+            "combo" if !option.vars.iter().any(|v| v == value) => Err(format!(
+                "{} is one of {}",
+                option.name,
+                option.vars.join(", ")
+            )),
+            "button" => Err(format!(
+                "{} is a button, which profiles do not store",
+                option.name
+            )),
+            _ => Ok(Value::from(value)),
+        }
+    }
+
+    pub fn set(&mut self, value: &str) {
+        let option = self.current();
+        match Self::validate(option, value) {
+            Ok(value) => {
+                let name = option.name.clone();
+                self.table_mut().insert(&name, Item::Value(value));
+                self.modified = true;
+                self.status.clear();
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    pub fn unset(&mut self) {
+        let name = self.current().name.clone();
+        if self.table_mut().remove(&name).is_some() {
+            self.modified = true;
+        }
+    }
+
+    /// The value in effect: the profile's, or else the inherited one, or
+    /// else the engine's default.
+    pub fn effective(&self, option: &AnnouncedOption) -> Option<String> {
+        self.value(&option.name)
+            .or_else(|| self.inherited(&option.name))
+            .or_else(|| option.default.clone())
+    }
+
+    pub fn adjust(&mut self, steps: i64, large: bool) {
+        let option = self.current();
+        let current = self.effective(option);
+
+        match &option.kind[..] {
+            "spin" => {
+                let min = option.min.as_deref().and_then(|m| m.parse::<i64>().ok());
+                let max = option.max.as_deref().and_then(|m| m.parse::<i64>().ok());
+                let step = match (large, min, max) {
+                    (true, Some(min), Some(max)) => ((max - min) / Self::SLIDER as i64).max(1),
+                    _ => 1,
+                };
+                let n = current.and_then(|c| c.parse::<i64>().ok()).unwrap_or(0) + steps * step;
+                let n = n.clamp(min.unwrap_or(i64::MIN), max.unwrap_or(i64::MAX));
+                self.set(&n.to_string());
+            }
+            "combo" if !option.vars.is_empty() => {
+                let vars = &option.vars;
+                let ix = vars.iter().position(|v| Some(v) == current.as_ref());
+                let next = match ix {
+                    Some(ix) => (ix as i64 + steps).rem_euclid(vars.len() as i64) as usize,
+                    None => 0,
+                };
+                let value = vars[next].clone();
+                self.set(&value);
+            }
+            "check" => self.activate(),
+            _ => {}
+        }
+    }
+
+    pub fn activate(&mut self) {
+        let option = self.current();
+        match &option.kind[..] {
+            "check" => {
+                let on = self.effective(option).as_deref() == Some("true");
+                self.set(if on { "false" } else { "true" });
+            }
+            "button" => self.set("true"),
+            _ => self.buffer = Some(self.effective(option).unwrap_or_default()),
+        }
+    }
+
+    pub fn slider(option: &AnnouncedOption, value: &str) -> String {
+        let parse = |s: Option<&str>| s.and_then(|s| s.parse::<f64>().ok());
+        let (Some(min), Some(max), Some(v)) = (
+            parse(option.min.as_deref()),
+            parse(option.max.as_deref()),
+            parse(Some(value)),
+        ) else {
+            return String::new();
+        };
+
+        let pos = if max > min {
+            (((v - min) / (max - min)) * (Self::SLIDER - 1) as f64).round() as usize
+        } else {
+            0
+        };
+        let mut res = String::from("[");
+        for ix in 0..Self::SLIDER {
+            res.push(if ix == pos { '\u{25C6}' } else { '\u{2500}' });
+        }
+        res.push(']');
+        res
+    }
+
+    pub fn render_option(&self, option: &AnnouncedOption) -> String {
+        let mark = if self.value(&option.name).is_some() {
+            '*'
+        } else if self.inherited(&option.name).is_some() {
+            '^'
+        } else {
+            ' '
+        };
+        let value = self.effective(option).unwrap_or_default();
+
+        let widget = match &option.kind[..] {
+            "spin" => format!(
+                "{} {value} ({}..{})",
+                Self::slider(option, &value),
+                option.min.as_deref().unwrap_or("?"),
+                option.max.as_deref().unwrap_or("?"),
+            ),
+            "check" => String::from(if value == "true" { "[x]" } else { "[ ]" }),
+            "combo" => format!("< {value} >"),
+            "button" => String::from("(button)"),
+            _ => format!("\"{value}\""),
+        };
+
+        format!("{mark} {:<28} {:<7} {widget}", option.name, option.kind)
+    }
+
+    pub async fn render(&self) -> tokio::io::Result<()> {
+        let mut res = vec![];
+
+        queue!(res, terminal::Clear(terminal::ClearType::All));
+
+        res.append(&mut Self::title_renderer().render(&format!(
+            "{}{}",
+            self.file.to_string_lossy(),
+            if self.modified { " (modified)" } else { "" }
+        )));
+
+        if self.preview {
+            res.append(&mut Self::row_renderer(0, false).render(&self.doc.to_string()));
+        } else {
+            for (row, option) in self
+                .options
+                .iter()
+                .enumerate()
+                .skip(self.offset)
+                .take(Self::ROWS)
+            {
+                let mut line = self.render_option(option);
+                if row == self.cursor {
+                    if let Some(buffer) = &self.buffer {
+                        line = format!("  {:<28} {:<7} {buffer}_", option.name, option.kind);
+                    }
+                }
+                res.append(
+                    &mut Self::row_renderer(row - self.offset, row == self.cursor).render(&line),
+                );
+            }
+        }
+
+        res.append(&mut Self::status_renderer().render(&self.status));
+
+        let keymap = Keymap::active();
+        res.append(&mut Self::reminder_renderer().render(&format!(
+            "{}: help, {}: exit, * set in profile, ^ inherited",
+            keymap.describe(Action::Help),
+            keymap.describe(Action::Quit),
+        )));
+
+        if self.help {
+            res.append(&mut Self::help_renderer().render_boxed("Keys", &Self::help_text()));
+        }
+
+        stdout().write_all(&res[..]).await?;
+
+        Ok(())
+    }
+
+    pub async fn write(&mut self) {
+        self.status = match tokio::fs::write(&self.file, self.doc.to_string()).await {
+            Ok(()) => {
+                self.modified = false;
+                format!("Written to {}", self.file.to_string_lossy())
+            }
+            Err(e) => format!("Unable to write: {e}"),
+        };
+    }
+
+    pub fn handle_buffer(&mut self, key: KeyEvent) {
+        let Some(buffer) = &mut self.buffer else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Char(c) => buffer.push(c),
+            KeyCode::Backspace => {
+                buffer.pop();
+            }
+            KeyCode::Esc => self.buffer = None,
+            KeyCode::Enter => {
+                let value = self.buffer.take().unwrap();
+                self.set(&value);
+            }
+            _ => {}
+        }
+    }
+
+    pub async fn handle(&mut self, ev: Event) -> bool {
+        let Event::Key(key) = ev else {
+            return false;
+        };
+        if !key.is_press() && !key.is_repeat() {
+            return false;
+        }
+
+        if self.help {
+            self.help = false;
+            return false;
+        }
+
+        if self.buffer.is_some() {
+            self.handle_buffer(key);
+            return false;
+        }
+
+        match Keymap::active().action(&key, &Self::ACTIONS) {
+            Some(Action::Decrease) => self.adjust(-1, false),
+            Some(Action::Increase) => self.adjust(1, false),
+            Some(Action::DecreaseMore) => self.adjust(-1, true),
+            Some(Action::IncreaseMore) => self.adjust(1, true),
+            Some(Action::Activate) => self.activate(),
+            Some(Action::RemoveOption) => self.unset(),
+            Some(Action::Preview) => self.preview = !self.preview,
+            Some(Action::WriteProfile) => self.write().await,
+            Some(Action::PrevMove) => self.cursor = self.cursor.saturating_sub(1),
+            Some(Action::NextMove) => {
+                self.cursor = (self.cursor + 1).min(self.options.len().saturating_sub(1))
+            }
+            Some(Action::Help) => self.help = true,
+            Some(Action::Suspend) => widgets::suspend(),
+            Some(Action::Quit) if self.modified && !self.quitting => {
+                self.quitting = true;
+                self.status = String::from("Unsaved changes, exit again to discard them");
+                return false;
+            }
+            Some(Action::Quit) => return true,
+            _ => {}
+        }
+        self.quitting = false;
+
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + Self::ROWS {
+            self.offset = self.cursor + 1 - Self::ROWS;
+        }
+
+        false
+    }
+
+    pub async fn mainloop(&mut self) -> tokio::io::Result<()> {
+        if self.options.is_empty() {
+            eprintln_async!("The engine announced no options").await;
+            return Ok(());
+        }
+
+        let _terminal = widgets::TerminalGuard::new()?;
+
+        let mut event_stream = EventStream::new().fuse();
+
+        loop {
+            self.render().await?;
+
+            match event_stream.next().await {
+                Some(ev) => {
+                    if self.handle(ev?).await {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt::Display;
+
+/// Game results from one player's point of view.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Tally {
+    /// Counts a game by the points the player got from it.
+    pub fn add(&mut self, points: f64) {
+        if points > 0.75 {
+            self.wins += 1;
+        } else if points > 0.25 {
+            self.draws += 1;
+        } else {
+            self.losses += 1;
+        }
+    }
+
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    /// Elo difference to the opponent, with the margin of a 95% confidence
+    /// interval around it.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return None;
+        }
+
+        let p = self.points() / n;
+        let variance = (self.wins as f64 * (1.0 - p).powi(2)
+            + self.draws as f64 * (0.5 - p).powi(2)
+            + self.losses as f64 * p.powi(2))
+            / n;
+        let error = (variance / n).sqrt();
+
+        // keep the estimate and bounds finite for perfect and zero scores
+        let low = elo_difference((p - 1.96 * error).clamp(0.001, 0.999));
+        let high = elo_difference((p + 1.96 * error).clamp(0.001, 0.999));
+        Some((elo_difference(p.clamp(0.001, 0.999)), (high - low) / 2.0))
+    }
+}
+
+impl Display for Tally {
+    /// `+3 =4 -1`, as match results are usually summarized.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/// The Elo difference at which the expected score is `p`.
+pub fn elo_difference(p: f64) -> f64 {
+    -400.0 * (1.0 / p - 1.0).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(wins: usize, draws: usize, losses: usize) -> Tally {
+        Tally {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn counts_by_points() {
+        let mut t = Tally::default();
+        for points in [1.0, 0.5, 0.5, 0.0, 1.0] {
+            t.add(points);
+        }
+        assert_eq!(t, tally(2, 2, 1));
+        assert_eq!(t.games(), 5);
+        assert_eq!(t.points(), 3.0);
+        assert_eq!(t.to_string(), "+2 =2 -1");
+    }
+
+    #[test]
+    fn elo_of_scores() {
+        assert_eq!(Tally::default().elo(), None);
+
+        let (even, margin) = tally(3, 4, 3).elo().unwrap();
+        assert!(even.abs() < 1e-9);
+        assert!(margin > 0.0);
+
+        // 75% is 191 Elo
+        let (elo, _) = tally(3, 0, 1).elo().unwrap();
+        assert!((elo - 190.85).abs() < 0.01, "{elo}");
+
+        let (more, narrower) = tally(30, 0, 10).elo().unwrap();
+        assert!((more - elo).abs() < 1e-9);
+        assert!(narrower < tally(3, 0, 1).elo().unwrap().1);
+    }
+
+    #[test]
+    fn elo_of_perfect_scores_is_finite() {
+        let (wins, margin) = tally(5, 0, 0).elo().unwrap();
+        assert!(wins.is_finite() && wins > 1000.0);
+        assert!(margin.is_finite());
+
+        let (losses, margin) = tally(0, 0, 5).elo().unwrap();
+        assert!((losses + wins).abs() < 1e-9);
+        assert!(margin.is_finite());
+    }
+}
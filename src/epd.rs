@@ -0,0 +1,457 @@
+use std::{collections::VecDeque, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::Parser;
+use mintymacks::{
+    deque,
+    game::GameState,
+    notation::{
+        LongAlg,
+        uci::{
+            engine::UciEngine,
+            gui::{GoCommand, UciGui},
+        },
+    },
+    utils::{eprintln_async, println_async},
+};
+use tokio::{
+    select,
+    time::{Instant, sleep},
+};
+
+use crate::{
+    Runnable, analysis::play_lan, faceoff::Contestant, search_info::SearchInfo, uci_log::UciLog,
+    widgets::move_select::game_from_fen,
+};
+
+#[derive(Parser)]
+pub struct EpdSuite {
+    /// Bot profile
+    pub profile: PathBuf,
+
+    /// EPD file with `bm` or `am` opcodes
+    pub file: PathBuf,
+
+    /// Search time per position in miliseconds
+    #[clap(long, default_value_t = 5000)]
+    pub time: u64,
+
+    /// Search to this depth with `go depth`, within the time
+    #[clap(long, conflicts_with = "nodes")]
+    pub depth: Option<u32>,
+
+    /// Search this many nodes with `go nodes`, within the time
+    #[clap(long)]
+    pub nodes: Option<u64>,
+
+    /// Directory for the UCI log, if the profile sets `log`
+    #[clap(long, default_value = ".")]
+    pub log_dir: PathBuf,
+}
+
+/// A test position with the opcodes the runner understands.
+pub struct EpdRecord {
+    pub fen: String,
+    pub id: Option<String>,
+    /// Best moves in SAN, any of which solves the position
+    pub bm: Vec<String>,
+    /// Moves to avoid in SAN
+    pub am: Vec<String>,
+    /// The `c0` comment, which STS uses for partial credit
+    pub comment: Option<String>,
+}
+
+impl EpdRecord {
+    /// Parses a line like `<board> <side> <castling> <ep> bm Qd1+; id "WAC.001";`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let mut fields = line.splitn(5, ' ');
+        let position = [fields.next()?, fields.next()?, fields.next()?, fields.next()?];
+        let rest = fields.next().unwrap_or_default();
+
+        let mut res = EpdRecord {
+            fen: String::new(),
+            id: None,
+            bm: vec![],
+            am: vec![],
+            comment: None,
+        };
+        let (mut halfmoves, mut fullmoves) = (String::from("0"), String::from("1"));
+
+        for operation in split_operations(rest) {
+            let (opcode, operand) = operation.split_once(' ').unwrap_or((operation, ""));
+            let operand = operand.trim();
+            match opcode {
+                "bm" => res.bm = operand.split_whitespace().map(String::from).collect(),
+                "am" => res.am = operand.split_whitespace().map(String::from).collect(),
+                "id" => res.id = Some(unquote(operand)),
+                "c0" => res.comment = Some(unquote(operand)),
+                "hmvc" => halfmoves = operand.to_string(),
+                "fmvn" => fullmoves = operand.to_string(),
+                _ => {}
+            }
+        }
+
+        res.fen = format!("{} {halfmoves} {fullmoves}", position.join(" "));
+        Some(res)
+    }
+
+    /// Points for a move in SAN: full marks for a best move or a move that
+    /// is not avoided, and the STS `c0` scores like `Nf3=10, Nd2=5` if given.
+    pub fn points(&self, san: &str) -> (u32, u32) {
+        if let Some(credits) = self.comment.as_deref().and_then(parse_credits) {
+            let max = credits.iter().map(|(_, p)| *p).max().unwrap_or(0);
+            let got = credits
+                .iter()
+                .find(|(mv, _)| same_san(mv, san))
+                .map(|(_, p)| *p)
+                .unwrap_or(0);
+            return (got, max);
+        }
+
+        (self.solved_by(san) as u32, 1)
+    }
+
+    pub fn solved_by(&self, san: &str) -> bool {
+        if !self.bm.is_empty() {
+            return self.bm.iter().any(|mv| same_san(mv, san));
+        }
+        !self.am.iter().any(|mv| same_san(mv, san))
+    }
+}
+
+/// Splits operations at semicolons that are not inside a quoted operand.
+fn split_operations(s: &str) -> Vec<&str> {
+    let mut res = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (ix, c) in s.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                res.push(s[start..ix].trim());
+                start = ix + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(s[start..].trim());
+    res.retain(|op| !op.is_empty());
+    res
+}
+
+fn unquote(s: &str) -> String {
+    s.trim_matches('"').to_string()
+}
+
+fn parse_credits(comment: &str) -> Option<Vec<(String, u32)>> {
+    comment
+        .split(',')
+        .map(|credit| {
+            let (mv, points) = credit.trim().split_once('=')?;
+            Some((mv.to_string(), points.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Compares moves in SAN, ignoring check and annotation marks.
+pub fn same_san(a: &str, b: &str) -> bool {
+    let strip = |s: &str| s.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+    strip(a) == strip(b)
+}
+
+/// How the engine did on one position.
+pub struct EpdResult {
+    /// The move played, in SAN
+    pub san: Option<String>,
+    pub points: (u32, u32),
+    /// When the engine's main line settled on a solution for good
+    pub solved_at: Option<Duration>,
+    pub info: Option<SearchInfo>,
+}
+
+impl Runnable for EpdSuite {
+    async fn run(self) -> tokio::io::Result<()> {
+        let text = String::from_utf8_lossy_owned(tokio::fs::read(&self.file).await?);
+        let records = text
+            .lines()
+            .filter_map(EpdRecord::parse)
+            .collect::<Vec<_>>();
+
+        if records.is_empty() {
+            eprintln_async!("No positions found").await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        let mut contestant = Contestant::load(&self.profile).await?;
+        if contestant.profile.engine.log {
+            let path = self.log_dir.join(format!(
+                "{}-epd.log",
+                UciLog::file_name(&contestant.profile.engine.name)
+            ));
+            let path = contestant.log.open(&path).await?;
+            eprintln_async!("Logging to {}", path.to_string_lossy()).await;
+        }
+
+        let (mut solved, mut points, mut max_points) = (0, 0, 0);
+        let mut solved_time = Duration::ZERO;
+
+        for (ix, record) in records.iter().enumerate() {
+            let name = record.id.clone().unwrap_or_else(|| format!("#{}", ix + 1));
+
+            let game = match game_from_fen(&record.fen) {
+                Ok(game) => game,
+                Err(e) => {
+                    println_async!("{name}: skipped, {e}").await;
+                    continue;
+                }
+            };
+
+            contestant.log.note(&format!("position {name}")).await?;
+            let result = self.search(&mut contestant, &game, record).await?;
+
+            points += result.points.0;
+            max_points += result.points.1;
+
+            let expected = if record.bm.is_empty() {
+                format!("avoid {}", record.am.join(" "))
+            } else {
+                format!("best {}", record.bm.join(" "))
+            };
+            let played = result.san.as_deref().unwrap_or("no move");
+            let status = match (result.san.as_deref(), result.solved_at) {
+                (Some(san), Some(at)) if record.solved_by(san) => {
+                    solved += 1;
+                    solved_time += at;
+                    format!("solved in {:.2}s", at.as_secs_f64())
+                }
+                _ => String::from("failed"),
+            };
+            let search = match &result.info {
+                Some(info) => format!(
+                    ", depth {}, {} nodes",
+                    info.depth.unwrap_or(0),
+                    info.nodes.unwrap_or(0)
+                ),
+                None => String::new(),
+            };
+
+            println_async!("{name}: {status}, played {played} ({expected}){search}").await;
+        }
+
+        println_async!(
+            "Solved {solved} of {}, {points} of {max_points} points",
+            records.len()
+        )
+        .await;
+        if solved > 0 {
+            println_async!(
+                "Average time to solution {:.2}s",
+                solved_time.as_secs_f64() / solved as f64
+            )
+            .await;
+        }
+
+        contestant.log.flush().await?;
+        contestant.engine.quit().await?;
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+impl EpdSuite {
+    /// Searches until the engine finishes its depth or node budget or the
+    /// time runs out, following the main line to tell when the engine found
+    /// the solution.
+    pub async fn search(
+        &self,
+        contestant: &mut Contestant,
+        game: &GameState,
+        record: &EpdRecord,
+    ) -> tokio::io::Result<EpdResult> {
+        let failed = EpdResult {
+            san: None,
+            points: (0, record.points("").1),
+            solved_at: None,
+            info: None,
+        };
+        if let Err(reason) = contestant.new_game().await? {
+            eprintln_async!("{}: {reason}", contestant.profile.engine.name).await;
+            contestant.restart().await?;
+            return Ok(failed);
+        }
+
+        let to_san = |lan: &str| play_lan(&mut game.clone(), lan);
+
+        let time = Duration::from_millis(self.time);
+        let go = match (self.depth, self.nodes) {
+            (Some(depth), _) => GoCommand::Depth(depth as u64),
+            (None, Some(nodes)) => GoCommand::Nodes(nodes),
+            (None, None) => GoCommand::Infinite(),
+        };
+        let mut outbox: VecDeque<UciGui> =
+            deque![UciGui::Position(game.uci_position(), game.uci_line()), UciGui::Go(go)];
+        let mut info: Option<SearchInfo> = None;
+        let mut solved_at = None;
+        let mut stopped = false;
+
+        let started = Instant::now();
+        let (mut ingress, mut egress) = contestant.engine.split();
+
+        let best = loop {
+            select! {
+                _ = sleep(Duration::from_millis(10)) => {}
+                uci = ingress.receive() => {
+                    if let Ok(msg) = &uci {
+                        contestant.log.received(msg).await?;
+                    }
+                    match uci {
+                        Ok(UciEngine::BestMove(bm)) => break Some(bm.best.0.longalg(bm.best.1)),
+                        Ok(other) => {
+                            // only the main line counts towards the solution
+                            let later = SearchInfo::from_uci(&other)
+                                .filter(|later| !later.multipv.is_some_and(|n| n > 1));
+                            if let Some(later) = later {
+                                if let Some(first) = later.pv.first() {
+                                    let solving = to_san(first).is_some_and(|san| record.solved_by(&san));
+                                    match (solving, solved_at) {
+                                        (true, None) => solved_at = Some(started.elapsed()),
+                                        (false, _) => solved_at = None,
+                                        _ => {}
+                                    }
+                                }
+
+                                match &mut info {
+                                    Some(info) => info.update(later),
+                                    None => info = Some(later),
+                                }
+                            }
+                        }
+                        Err(e) if e.kind() == tokio::io::ErrorKind::InvalidData => {}
+                        Err(_) => break None,
+                    }
+                }
+                sent = egress.send(outbox.front()), if !outbox.is_empty() => {
+                    if sent.is_err() {
+                        break None;
+                    }
+                    if let Some(cmd) = outbox.pop_front() {
+                        contestant.log.sent(&cmd).await?;
+                    }
+                }
+            }
+
+            if started.elapsed() >= time && !stopped {
+                outbox.push_back(UciGui::Stop());
+                stopped = true;
+            }
+
+            // give up on engines that ignore `stop'
+            if started.elapsed() > time * 2 + Duration::from_secs(1) {
+                break None;
+            }
+        };
+
+        if best.is_none() {
+            contestant.restart().await?;
+        }
+        let san = best.as_deref().and_then(to_san);
+
+        // the final move may differ from the last main line
+        let solved = san.as_deref().is_some_and(|san| record.solved_by(san));
+        Ok(EpdResult {
+            points: san
+                .as_deref()
+                .map(|san| record.points(san))
+                .unwrap_or((0, record.points("").1)),
+            solved_at: if solved {
+                solved_at.or(Some(started.elapsed()))
+            } else {
+                None
+            },
+            san,
+            info,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_record() {
+        let record = EpdRecord::parse(
+            r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#,
+        )
+        .unwrap();
+        assert_eq!(
+            record.fen,
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(record.bm, ["Qg6"]);
+        assert!(record.am.is_empty());
+        assert_eq!(record.id.as_deref(), Some("WAC.001"));
+    }
+
+    #[test]
+    fn move_counters() {
+        let record =
+            EpdRecord::parse("8/8/8/8/8/8/8/K6k b - - hmvc 12; fmvn 40; am Kg2 Kh2;").unwrap();
+        assert_eq!(record.fen, "8/8/8/8/8/8/8/K6k b - - 12 40");
+        assert_eq!(record.am, ["Kg2", "Kh2"]);
+    }
+
+    #[test]
+    fn quoted_semicolons() {
+        let record = EpdRecord::parse(
+            r#"8/8/8/8/8/8/8/K6k w - - bm Ka2; id "odd; name"; c0 "Ka2=10, Kb1=3";"#,
+        )
+        .unwrap();
+        assert_eq!(record.id.as_deref(), Some("odd; name"));
+        assert_eq!(record.comment.as_deref(), Some("Ka2=10, Kb1=3"));
+    }
+
+    #[test]
+    fn not_a_record() {
+        assert!(EpdRecord::parse("").is_none());
+        assert!(EpdRecord::parse("# comment").is_none());
+        assert!(EpdRecord::parse("8/8/8/8/8/8/8/K6k w").is_none());
+    }
+
+    #[test]
+    fn credits() {
+        assert_eq!(
+            parse_credits("Nf3=10, Nd2=5"),
+            Some(vec![(String::from("Nf3"), 10), (String::from("Nd2"), 5)])
+        );
+        assert_eq!(parse_credits("a good move"), None);
+        assert_eq!(parse_credits("Nf3=ten"), None);
+    }
+
+    #[test]
+    fn points() {
+        let sts =
+            EpdRecord::parse(r#"8/8/8/8/8/8/8/K6k w - - bm Ka2; c0 "Ka2=10, Kb1=3";"#).unwrap();
+        assert_eq!(sts.points("Ka2"), (10, 10));
+        assert_eq!(sts.points("Kb1+"), (3, 10));
+        assert_eq!(sts.points("Kb2"), (0, 10));
+
+        let avoid = EpdRecord::parse("8/8/8/8/8/8/8/K6k w - - am Kb1;").unwrap();
+        assert_eq!(avoid.points("Ka2"), (1, 1));
+        assert_eq!(avoid.points("Kb1"), (0, 1));
+    }
+
+    #[test]
+    fn san_comparison() {
+        assert!(same_san("Qg6", "Qg6+"));
+        assert!(same_san("Qxf7#", "Qxf7"));
+        assert!(same_san("Nf3!?", "Nf3"));
+        assert!(same_san("0-0", "O-O"));
+        assert!(same_san("0-0-0+", "O-O-O"));
+        assert!(!same_san("O-O", "O-O-O"));
+        assert!(!same_san("Nbd2", "Nd2"));
+    }
+}
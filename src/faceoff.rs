@@ -1,8 +1,8 @@
 use std::{
     collections::{HashMap, VecDeque},
-    fmt::format,
-    path::PathBuf,
-    process::{self, ExitCode},
+    fmt::{Display, format},
+    path::{Path, PathBuf},
+    process::{ExitCode, Stdio},
     time::Duration,
 };
 
@@ -11,10 +11,10 @@ use crossterm::{cursor, execute, style, terminal};
 use mintymacks::{
     bits::board::{self, BitBoard},
     deque,
-    engine::{EngineHandle, load_engine},
+    engine::EngineHandle,
     game::GameState,
     model::{
-        ChessPiece, Victory, WinReason,
+        ChessPiece, Color, Victory, WinReason,
         moves::{ChessMove, PseudoMove},
     },
     notation::{
@@ -34,22 +34,40 @@ use mintymacks::{
 };
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, stdin, stdout},
+    process::{ChildStderr, Command},
     select,
-    time::{Instant, sleep},
+    sync::mpsc::{UnboundedReceiver, unbounded_channel},
+    time::{Instant, sleep, sleep_until},
 };
 
-use crate::Runnable;
+use crate::{
+    Runnable,
+    adjudicate::{Adjudication, AdjudicationRules, Adjudicator},
+    match_state::MatchState,
+    registry::{load_table, parse_overlay, resolve_profile},
+    results::{RecordOptions, identity, record},
+    search_info::{Score, SearchInfo},
+    uci_log::UciLog,
+};
 
 #[derive(Parser)]
 pub struct Faceoff {
-    /// Bot profile
+    /// Bot profile, by file or by name
     #[clap(long)]
     pub white: PathBuf,
 
-    /// Bot profile
+    /// Bot profile, by file or by name
     #[clap(long)]
     pub black: PathBuf,
 
+    /// Option for the white profile, like `"Skill Level=5"`, may be repeated
+    #[clap(long, value_parser = parse_overlay)]
+    pub white_opt: Vec<(String, toml::Value)>,
+
+    /// Option for the black profile, like `"Skill Level=5"`, may be repeated
+    #[clap(long, value_parser = parse_overlay)]
+    pub black_opt: Vec<(String, toml::Value)>,
+
     /// Turn time in miliseconds
     #[clap(long)]
     pub time: u64,
@@ -57,135 +75,867 @@ pub struct Faceoff {
     /// Turn timeout in miliseconds
     #[clap(long)]
     pub timeout: u64,
+
+    /// Number of games, alternating colors after each
+    #[clap(long, default_value_t = 1)]
+    pub games: usize,
+
+    #[clap(flatten)]
+    pub adjudication: AdjudicationRules,
+
+    /// Directory for the UCI logs of engines whose profile sets `log`
+    #[clap(long, default_value = ".")]
+    pub log_dir: PathBuf,
+
+    #[clap(flatten)]
+    pub record: RecordOptions,
+
+    /// File to write the match state to after every game
+    #[clap(long, default_value = "fight-state.toml")]
+    pub state: PathBuf,
+
+    /// Continue the match in a state file from its next unplayed game,
+    /// given the same profiles and times. The number of games and the
+    /// adjudication rules are those of the state
+    #[clap(long, conflicts_with_all = [
+        "state",
+        "games",
+        "resign_score",
+        "resign_moves",
+        "draw_score",
+        "draw_moves",
+        "draw_after",
+        "tablebase",
+    ])]
+    pub resume: Option<PathBuf>,
 }
 
 impl Runnable for Faceoff {
     async fn run(self) -> tokio::io::Result<()> {
         eprintln_async!("Loading profiles...").await;
-        let white_profile = tokio::fs::read(&self.white).await?;
-        let black_profile = tokio::fs::read(&self.black).await?;
+        let white = with_overlay(read_profile(&self.white).await?, &self.white_opt);
+        let black = with_overlay(read_profile(&self.black).await?, &self.black_opt);
+        let ids = [
+            identity(&self.white, &self.white_opt).await?,
+            identity(&self.black, &self.black_opt).await?,
+        ];
+        let results = self.record.path();
 
-        let bad_file = |_| tokio::io::Error::from(tokio::io::ErrorKind::InvalidData);
+        let fresh = MatchState::new(
+            self.time,
+            self.timeout,
+            self.games,
+            self.adjudication.clone(),
+            &white,
+            &black,
+        )?;
+        let (mut state, state_path) = match &self.resume {
+            Some(path) => {
+                let state = MatchState::load(path).await?;
+                if let Some(e) = state.mismatch(&fresh) {
+                    eprintln_async!("Cannot resume {}: {e}", path.to_string_lossy()).await;
+                    ExitCode::FAILURE.exit_process();
+                }
+                (state, path.clone())
+            }
+            None => (fresh, self.state.clone()),
+        };
 
-        let white_profile: EngineProfile = toml::from_slice(&white_profile).map_err(bad_file)?;
-        let black_profile: EngineProfile = toml::from_slice(&black_profile).map_err(bad_file)?;
+        let mut first = Contestant::from_profile(white).await?;
+        let mut second = Contestant::from_profile(black).await?;
+        [first.score, second.score] = state.scores;
+        [first.crashes, second.crashes] = state.crashes;
 
-        eprintln_async!("Loading white engine...").await;
-        let (mut white_engine, mut white_details) = load_engine(&white_profile).await?;
-        eprintln_async!("Loading black engine...").await;
-        let (mut black_engine, mut black_details) = load_engine(&black_profile).await?;
+        // so that the PGN output is complete however often the match stopped
+        for pgn in &state.played {
+            println_async!("{pgn}").await;
+        }
+        let games = state.games;
+        if state.played.len() >= games {
+            eprintln_async!("All {games} games were already played").await;
+        }
 
-        eprintln_async!("Initializing white engine...").await;
-        let mut ingress = vec![];
-        white_engine
-            .interleave_until(
-                &mut deque![UciGui::UciNewGame(), UciGui::IsReady()],
-                &mut ingress,
-                |x| x == &UciEngine::ReadyOk(),
-                Duration::from_millis(1000),
-            )
-            .await?;
-        if ingress.last() != Some(&UciEngine::ReadyOk()) {
-            eprintln_async!("Engine did not respond `readyok' in time.").await;
-            ExitCode::FAILURE.exit_process();
+        let time = Duration::from_millis(self.time);
+        let timeout = Duration::from_millis(self.timeout);
+        let adjudicator = Adjudicator::new(state.adjudication.clone())?;
+
+        for round in state.played.len() + 1..=games {
+            let (white, black, white_id, black_id) = if round % 2 == 1 {
+                (&mut first, &mut second, &ids[0], &ids[1])
+            } else {
+                (&mut second, &mut first, &ids[1], &ids[0])
+            };
+
+            eprintln_async!("Starting game {round} of {games}...").await;
+            white.start_log(&self.log_dir, round, "white").await?;
+            black.start_log(&self.log_dir, round, "black").await?;
+            let game = MatchGame::play(white, black, &adjudicator, time, timeout).await?;
+            white.log.flush().await?;
+            black.log.flush().await?;
+
+            let (w, b) = game.points();
+            white.score += w;
+            black.score += b;
+
+            let pgn = game.pgn(round);
+            println_async!("{pgn}").await;
+
+            state.played.push(pgn);
+            state.scores = [first.score, second.score];
+            state.crashes = [first.crashes, second.crashes];
+            state.write(&state_path).await?;
+
+            // only after the state, so that a resumed match cannot record
+            // the same game twice
+            if let Some(path) = &results {
+                record(path, white_id, black_id, w).await?;
+            }
         }
 
-        eprintln_async!("Initializing black engine...").await;
-        ingress.clear();
-        black_engine
+        eprintln_async!(
+            "Score: {} {} - {} {}",
+            first.profile.engine.name,
+            first.score,
+            second.score,
+            second.profile.engine.name,
+        )
+        .await;
+        eprintln_async!(
+            "Crashes: {} {}, {} {}",
+            first.profile.engine.name,
+            first.crashes,
+            second.profile.engine.name,
+            second.crashes,
+        )
+        .await;
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+/// Reads a profile from a file, or from the registry if there is no such
+/// file, with the profile it extends filled in underneath.
+pub async fn read_profile(path: &Path) -> tokio::io::Result<EngineProfile> {
+    let table = load_table(&resolve_profile(path).await?).await?;
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|_| tokio::io::Error::from(tokio::io::ErrorKind::InvalidData))
+}
+
+/// Sets options over those of the profile.
+pub fn with_overlay(
+    mut profile: EngineProfile,
+    overlay: &[(String, toml::Value)],
+) -> EngineProfile {
+    for (name, value) in overlay {
+        profile.options.insert(name.clone(), value.clone());
+    }
+    profile
+}
+
+/// An engine taking part in a match, restarted from its profile if it
+/// crashes.
+pub struct Contestant {
+    pub profile: EngineProfile,
+    pub engine: EngineHandle,
+    pub score: f64,
+    pub crashes: usize,
+    /// The reply whose ponder move the engine is searching on the
+    /// opponent's time
+    pub pondering: Option<BestMove>,
+    pub log: UciLog,
+    pub stderr: StderrTail,
+    /// Search output the engine sent before finishing the handshake, which
+    /// forfeits its next move
+    pub premature: Vec<String>,
+    /// Why the engine could not be restarted after a crash; it is tried
+    /// again before its next game, which it forfeits if that fails too
+    pub down: Option<String>,
+}
+
+impl Contestant {
+    pub async fn load(path: &Path) -> tokio::io::Result<Self> {
+        Self::from_profile(read_profile(path).await?).await
+    }
+
+    pub async fn from_profile(profile: EngineProfile) -> tokio::io::Result<Self> {
+        eprintln_async!("Loading {}...", profile.engine.name).await;
+        // logs are opened per game, so the start-up waits for the first one
+        let mut log = if profile.engine.log {
+            UciLog::buffered()
+        } else {
+            UciLog::disabled()
+        };
+        let (engine, stderr, premature) = launch(&profile, &mut log).await?;
+
+        Ok(Contestant {
+            profile,
+            engine,
+            score: 0.0,
+            crashes: 0,
+            pondering: None,
+            log,
+            stderr,
+            premature,
+            down: None,
+        })
+    }
+
+    /// Starts the engine again, setting `down` if that fails.
+    pub async fn restart(&mut self) -> tokio::io::Result<()> {
+        eprintln_async!("Restarting {}...", self.profile.engine.name).await;
+        self.log.note("restarting").await?;
+        self.pondering = None;
+        match launch(&self.profile, &mut self.log).await {
+            Ok((engine, stderr, premature)) => {
+                (self.engine, self.stderr, self.premature) = (engine, stderr, premature);
+                self.down = None;
+            }
+            Err(e) => {
+                eprintln_async!("Could not restart {}: {e}", self.profile.engine.name).await;
+                self.log.note(&format!("restart failed: {e}")).await?;
+                self.down = Some(e.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports what the engine wrote to stderr since the last call, and
+    /// returns it.
+    pub async fn check_stderr(&mut self) -> tokio::io::Result<Vec<String>> {
+        let lines = self.stderr.lines();
+        for line in &lines {
+            eprintln_async!("{} stderr: {line}", self.profile.engine.name).await;
+            self.log.note(&format!("stderr: {line}")).await?;
+        }
+        Ok(lines)
+    }
+
+    /// Opens the log for a new game, if the profile asks for one.
+    pub async fn start_log(
+        &mut self,
+        dir: &Path,
+        round: usize,
+        color: &str,
+    ) -> tokio::io::Result<()> {
+        if !self.profile.engine.log {
+            return Ok(());
+        }
+
+        let path = UciLog::path(dir, &self.profile.engine.name, round, color);
+        let path = self.log.open(&path).await?;
+        eprintln_async!(
+            "Logging {} to {}",
+            self.profile.engine.name,
+            path.to_string_lossy()
+        )
+        .await;
+        self.log
+            .note(&format!(
+                "{}, game {round}, {color}",
+                self.profile.engine.name
+            ))
+            .await
+    }
+
+    /// Whether the profile enables the `Ponder` option.
+    pub fn ponders(&self) -> bool {
+        self.profile
+            .options
+            .get("Ponder")
+            .is_some_and(|v| v.to_string() == "true")
+    }
+
+    /// Starts searching the expected reply to `bm` on the opponent's time.
+    pub async fn ponder(&mut self, game: &GameState, bm: BestMove) -> tokio::io::Result<()> {
+        let Some(expected) = bm.ponder else {
+            return Ok(());
+        };
+        let mut next = game.clone();
+        let Ok(fm) = next.find_move(expected) else {
+            return Ok(());
+        };
+        next.apply(fm);
+
+        let (_, mut egress) = self.engine.split();
+        for cmd in [
+            UciGui::Position(next.uci_position(), next.uci_line()),
+            UciGui::Go(GoCommand::Ponder()),
+        ] {
+            self.log.sent(&cmd).await?;
+            egress.send(Some(&cmd)).await?;
+        }
+
+        self.pondering = Some(bm);
+        Ok(())
+    }
+
+    /// Ends a ponder search whose move was not played, discarding its result.
+    pub async fn stop_pondering(&mut self) -> tokio::io::Result<()> {
+        if self.pondering.take().is_none() {
+            return Ok(());
+        }
+
+        let timeout = Duration::from_millis(500);
+        query_best_move(
+            &mut self.engine,
+            &mut self.log,
+            deque![UciGui::Stop()],
+            timeout,
+            timeout,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Starts a new game, returning any search output the engine sent
+    /// while it was not searching, or why the engine is not ready to play.
+    pub async fn new_game(&mut self) -> tokio::io::Result<Result<Vec<String>, String>> {
+        eprintln_async!("Initializing {}...", self.profile.engine.name).await;
+        let mut ingress = vec![];
+        let mut commands = deque![UciGui::UciNewGame(), UciGui::IsReady()];
+        for cmd in &commands {
+            self.log.sent(cmd).await?;
+        }
+        let exchanged = self
+            .engine
             .interleave_until(
-                &mut deque![UciGui::UciNewGame(), UciGui::IsReady()],
+                &mut commands,
                 &mut ingress,
                 |x| x == &UciEngine::ReadyOk(),
                 Duration::from_millis(1000),
             )
-            .await?;
+            .await;
+        for msg in &ingress {
+            self.log.received(msg).await?;
+        }
+        if let Err(e) = exchanged {
+            return Ok(Err(e.to_string()));
+        }
         if ingress.last() != Some(&UciEngine::ReadyOk()) {
-            eprintln_async!("Engine did not respond `readyok' in time.").await;
-            ExitCode::FAILURE.exit_process();
+            return Ok(Err(String::from("no `readyok' in time")));
         }
 
-        eprintln_async!("Starting game...").await;
+        Ok(Ok(ingress
+            .iter()
+            .filter(|x| matches!(x, UciEngine::Info(..) | UciEngine::BestMove(..)))
+            .map(|x| x.to_string())
+            .collect()))
+    }
+}
 
-        let mut game = GameState::startpos();
-        game.white = Some(mintymacks::profile::Profile::Engine(white_profile.clone()));
-        game.black = Some(mintymacks::profile::Profile::Engine(black_profile.clone()));
+/// Starts an engine and sets the options of its profile like `load_engine`,
+/// but logging the handshake. Also returns its stderr and any search output
+/// sent before `uciok`.
+pub async fn launch(
+    profile: &EngineProfile,
+    log: &mut UciLog,
+) -> tokio::io::Result<(EngineHandle, StderrTail, Vec<String>)> {
+    let (path, args) = &profile.engine.command;
+    let mut child = Command::new(path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stderr = match child.stderr.take() {
+        Some(pipe) => StderrTail::new(pipe),
+        None => return Err(tokio::io::Error::other("Engine stderr was not piped")),
+    };
+    let mut engine = EngineHandle::from_child(child)?;
 
-        let time = Duration::from_millis(self.time);
-        let timeout = Duration::from_millis(self.timeout);
+    // banners are fine, `info' before `uci' is not
+    let mut premature = unprompted(&mut engine, log, Duration::from_millis(100)).await?;
 
-        for (k, v) in game.pgn_header().0 {
-            println_async!("[{k} \"{v}\"]").await;
-        }
-        println_async!().await;
+    let handshake = exchange(
+        &mut engine,
+        log,
+        deque![UciGui::Uci()],
+        |x| x == &UciEngine::UciOk(),
+        Duration::from_millis(2000),
+    )
+    .await?;
+    premature.extend(
+        handshake
+            .iter()
+            .filter(|x| matches!(x, UciEngine::Info(..) | UciEngine::BestMove(..)))
+            .map(|x| x.to_string()),
+    );
 
-        loop {
-            if let Some(v) = game.outcome {
-                println_async!("{}", v.to_string()).await;
-                break;
-            } else {
-                println_async!().await;
+    let mut commands = profile
+        .options
+        .iter()
+        .map(|(name, value)| UciGui::SetOption(name.clone(), Some(option_value(value))))
+        .collect::<VecDeque<_>>();
+    commands.push_back(UciGui::IsReady());
+    exchange(
+        &mut engine,
+        log,
+        commands,
+        |x| x == &UciEngine::ReadyOk(),
+        Duration::from_millis(1000),
+    )
+    .await?;
+
+    Ok((engine, stderr, premature))
+}
+
+/// Search output the engine sends unasked within `wait`.
+async fn unprompted(
+    engine: &mut EngineHandle,
+    log: &mut UciLog,
+    wait: Duration,
+) -> tokio::io::Result<Vec<String>> {
+    let deadline = Instant::now() + wait;
+    let (mut ingress, _) = engine.split();
+    let mut res = vec![];
+
+    loop {
+        select! {
+            _ = sleep_until(deadline) => return Ok(res),
+            uci = ingress.receive() => match uci {
+                Ok(msg) => {
+                    log.received(&msg).await?;
+                    if matches!(msg, UciEngine::Info(..) | UciEngine::BestMove(..)) {
+                        res.push(msg.to_string());
+                    }
+                }
+                Err(e) if e.kind() == tokio::io::ErrorKind::InvalidData => {}
+                // a dead engine fails the handshake right after
+                Err(_) => return Ok(res),
             }
+        }
+    }
+}
+
+/// Sends the commands and reads until a message satisfies `done`, logging
+/// both ways, and fails if none does in time.
+pub async fn exchange(
+    engine: &mut EngineHandle,
+    log: &mut UciLog,
+    mut commands: VecDeque<UciGui>,
+    done: impl Fn(&UciEngine) -> bool,
+    limit: Duration,
+) -> tokio::io::Result<Vec<UciEngine>> {
+    for cmd in &commands {
+        log.sent(cmd).await?;
+    }
+    let mut ingress = vec![];
+    engine
+        .interleave_until(&mut commands, &mut ingress, &done, limit)
+        .await?;
+    for msg in &ingress {
+        log.received(msg).await?;
+    }
+
+    if !ingress.last().is_some_and(&done) {
+        return Err(tokio::io::Error::new(
+            tokio::io::ErrorKind::TimedOut,
+            "Engine did not answer in time",
+        ));
+    }
+    Ok(ingress)
+}
 
-            print_async!("{}. ", game.board.metadata.turn).await;
+/// An option value as `setoption` sends it.
+pub fn option_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// What an engine writes to stderr, read from a pipe in the background
+/// since the engine handle only reads stdout.
+pub struct StderrTail {
+    pub lines: UnboundedReceiver<String>,
+}
 
-            if let Some(m) = query_best_move(&mut white_engine, &mut game, time, timeout).await? {
-                if let Ok(fm) = game.find_move(m.best) {
-                    print_async!("{} ", game.apply(fm).unwrap().algebraic.to_string()).await;
-                } else {
-                    game.outcome = Some(Victory::BlackWins(WinReason::Forefeit));
-                    print_async!("{}", m.best.0.longalg(m.best.1)).await;
+impl StderrTail {
+    pub fn new(pipe: ChildStderr) -> Self {
+        let (sender, lines) = unbounded_channel();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(pipe).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                if sender.send(line).is_err() {
+                    break;
                 }
-            } else {
-                game.outcome = Some(Victory::BlackWins(WinReason::Time));
             }
+        });
+        StderrTail { lines }
+    }
 
-            if let Some(v) = game.outcome {
-                println_async!("{}", v.to_string()).await;
-                break;
+    /// Complete lines written since the last call.
+    pub fn lines(&mut self) -> Vec<String> {
+        let mut res = vec![];
+        while let Ok(line) = self.lines.try_recv() {
+            res.push(line);
+        }
+        res
+    }
+}
+
+/// A finished game and the movetext emitted while playing it.
+pub struct MatchGame {
+    pub game: GameState,
+    pub movetext: String,
+    pub termination: Option<&'static str>,
+    /// Reported score after every move, from white's point of view
+    pub scores: Vec<Option<Score>>,
+    /// Last search information the engine sent for every move
+    pub infos: Vec<Option<SearchInfo>>,
+    pub adjudication: Option<Adjudication>,
+}
+
+impl MatchGame {
+    pub async fn play(
+        white: &mut Contestant,
+        black: &mut Contestant,
+        adjudicator: &Adjudicator,
+        time: Duration,
+        timeout: Duration,
+    ) -> tokio::io::Result<Self> {
+        let mut res = MatchGame {
+            game: GameState::startpos(),
+            movetext: String::new(),
+            termination: None,
+            scores: vec![],
+            infos: vec![],
+            adjudication: None,
+        };
+
+        res.game.white = Some(mintymacks::profile::Profile::Engine(white.profile.clone()));
+        res.game.black = Some(mintymacks::profile::Profile::Engine(black.profile.clone()));
+
+        for (engine, color) in [(&mut *white, Color::White), (&mut *black, Color::Black)] {
+            if engine.down.is_some() {
+                engine.restart().await?;
             }
+            let failure = match &engine.down {
+                Some(reason) => Some(format!("could not be restarted: {reason}")),
+                None => match engine.new_game().await? {
+                    Ok(unsolicited) => {
+                        for line in unsolicited {
+                            res.movetext += &format!(
+                                "{{{}: {}}} ",
+                                engine.profile.engine.name,
+                                Violation::Unsolicited(line)
+                            );
+                        }
+                        None
+                    }
+                    Err(reason) => {
+                        engine.crashes += 1;
+                        engine.restart().await?;
+                        Some(format!("did not start the game: {reason}"))
+                    }
+                },
+            };
 
-            if let Some(m) = query_best_move(&mut black_engine, &mut game, time, timeout).await? {
-                if let Ok(fm) = game.find_move(m.best) {
-                    print_async!("{} ", game.apply(fm).unwrap().algebraic.to_string()).await;
-                } else {
-                    game.outcome = Some(Victory::WhiteWins(WinReason::Forefeit));
-                    print_async!("{}", m.best.0.longalg(m.best.1)).await;
-                }
+            if let Some(failure) = failure {
+                res.game.outcome = Some(defeat(color, WinReason::Forefeit));
+                res.termination = Some("abandoned");
+                res.movetext += &format!(
+                    "{{{} {}}} ",
+                    engine.profile.engine.name,
+                    failure.replace(['{', '}'], "")
+                );
+                return Ok(res);
+            }
+        }
+
+        let mut to_move = Color::White;
+        let mut last_best = None;
+
+        while res.game.outcome.is_none() && res.adjudication.is_none() {
+            let ply = res.scores.len() + 1;
+            white.log.move_marker(ply).await?;
+            black.log.move_marker(ply).await?;
+
+            let mover = match to_move {
+                Color::White => &mut *white,
+                Color::Black => &mut *black,
+            };
+
+            if to_move == Color::White {
+                res.movetext += &format!("{}. ", res.game.board.metadata.turn);
+            }
+
+            let position = UciGui::Position(res.game.uci_position(), res.game.uci_line());
+            let ponderhit = last_best.is_some()
+                && mover
+                    .pondering
+                    .as_ref()
+                    .is_some_and(|bm| bm.ponder == last_best);
+            let commands = if ponderhit {
+                mover.pondering = None;
+                deque![UciGui::PonderHit()]
+            } else {
+                mover.stop_pondering().await?;
+                deque![position.clone(), UciGui::Go(GoCommand::Infinite())]
+            };
+
+            // pondering is free, the clock starts at `ponderhit'
+            let started = Instant::now();
+            let reply = if mover.premature.is_empty() {
+                query_best_move(&mut mover.engine, &mut mover.log, commands, time, timeout).await?
             } else {
-                game.outcome = Some(Victory::WhiteWins(WinReason::Time));
+                let lines = std::mem::take(&mut mover.premature);
+                Reply::Violation(Violation::Premature(lines.join("; ")))
+            };
+            let elapsed = started.elapsed();
+            let stderr = mover.check_stderr().await?;
+
+            let violation = match reply {
+                Reply::Move(m, _) if m.best.0.from == m.best.0.to => Some(Violation::NullMove),
+                Reply::Move(m, info) => {
+                    if let Ok(fm) = res.game.find_move(m.best.clone()) {
+                        let fm = res.game.apply(fm).unwrap();
+                        res.movetext += &format!("{} ", fm.algebraic.to_string());
+                        if let Some(info) = &info {
+                            res.movetext += &format!("{{{}}} ", move_comment(info, elapsed));
+                        }
+
+                        let score = info.as_ref().and_then(|i| i.score);
+                        res.scores.push(match to_move {
+                            Color::White => score,
+                            Color::Black => score.map(Score::negate),
+                        });
+                        res.infos.push(info);
+
+                        if res.game.outcome.is_none() {
+                            res.adjudication = adjudicator.check(&res.game, &res.scores);
+                        }
+                        if let Some(adjudication) = &res.adjudication {
+                            res.termination = Some("adjudication");
+                            res.movetext += &format!("{{{}}} ", adjudication.reason);
+                        }
+
+                        last_best = Some(m.best.clone());
+                        if mover.ponders() && res.game.outcome.is_none() {
+                            mover.ponder(&res.game, m).await?;
+                        }
+
+                        None
+                    } else {
+                        Some(Violation::Illegal(m.best.0.longalg(m.best.1)))
+                    }
+                }
+                Reply::Violation(v) => Some(v),
+                Reply::Timeout => {
+                    res.game.outcome = Some(defeat(to_move, WinReason::Time));
+                    None
+                }
+                Reply::Crashed(reason) => {
+                    res.game.outcome = Some(defeat(to_move, WinReason::Forefeit));
+                    res.termination = Some("abandoned");
+                    let last_words = match stderr.last() {
+                        Some(line) => format!("; stderr: {line}"),
+                        None => String::new(),
+                    };
+                    res.movetext += &format!(
+                        "{{{} crashed: {}{}}} ",
+                        mover.profile.engine.name,
+                        reason.replace(['{', '}'], ""),
+                        last_words.replace(['{', '}'], "")
+                    );
+                    mover.crashes += 1;
+                    mover.restart().await?;
+                    None
+                }
+            };
+
+            if let Some(violation) = violation {
+                res.game.outcome = Some(defeat(to_move, WinReason::Forefeit));
+                res.termination = Some("rules infraction");
+                res.movetext += &format!(
+                    "{{{} forfeits: {}; FEN: {}; last command: {}}} ",
+                    mover.profile.engine.name,
+                    violation.to_string().replace(['{', '}'], ""),
+                    render_fen(&res.game.board),
+                    position,
+                );
             }
+
+            to_move = match to_move {
+                Color::White => Color::Black,
+                Color::Black => Color::White,
+            };
         }
 
-        ExitCode::SUCCESS.exit_process();
+        white.stop_pondering().await?;
+        black.stop_pondering().await?;
+
+        Ok(res)
+    }
+
+    /// Points scored by white and black.
+    pub fn points(&self) -> (f64, f64) {
+        if let Some(adjudication) = &self.adjudication {
+            return match adjudication.winner {
+                Some(Color::White) => (1.0, 0.0),
+                Some(Color::Black) => (0.0, 1.0),
+                None => (0.5, 0.5),
+            };
+        }
+
+        match self.game.outcome {
+            Some(Victory::WhiteWins(_)) => (1.0, 0.0),
+            Some(Victory::BlackWins(_)) => (0.0, 1.0),
+            _ => (0.5, 0.5),
+        }
+    }
+
+    pub fn result(&self) -> String {
+        if let Some(adjudication) = &self.adjudication {
+            return String::from(match adjudication.winner {
+                Some(Color::White) => "1-0",
+                Some(Color::Black) => "0-1",
+                None => "1/2-1/2",
+            });
+        }
+
+        self.game
+            .outcome
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| String::from("*"))
+    }
+
+    pub fn tags(&self, round: usize) -> Vec<(String, String)> {
+        let mut tags = self
+            .game
+            .pgn_header()
+            .0
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+
+        set_tag(&mut tags, "Round", round.to_string());
+        set_tag(&mut tags, "Result", self.result());
+        if let Some(termination) = self.termination {
+            set_tag(&mut tags, "Termination", termination.to_string());
+        }
+
+        tags
+    }
+
+    pub fn pgn(&self, round: usize) -> String {
+        let mut res = String::new();
+        for (k, v) in self.tags(round) {
+            res += &format!("[{k} \"{v}\"]\n");
+        }
+        res += &format!("\n{}{}\n", self.movetext, self.result());
+        res
     }
 }
 
+/// A cutechess-style move comment: score from the mover's point of view,
+/// depth and time spent, like `+0.34/21 1.2s`.
+pub fn move_comment(info: &SearchInfo, elapsed: Duration) -> String {
+    let mut res = match info.score {
+        Some(score) => score.to_string(),
+        None => String::from("?"),
+    };
+    if let Some(depth) = info.depth {
+        res += &format!("/{depth}");
+    }
+    res += &format!(" {:.1}s", elapsed.as_secs_f64());
+    res
+}
+
+pub fn set_tag(tags: &mut Vec<(String, String)>, key: &str, value: String) {
+    match tags.iter_mut().find(|(k, _)| k == key) {
+        Some((_, v)) => *v = value,
+        None => tags.push((key.to_string(), value)),
+    }
+}
+
+pub fn defeat(loser: Color, reason: WinReason) -> Victory {
+    match loser {
+        Color::White => Victory::BlackWins(reason),
+        Color::Black => Victory::WhiteWins(reason),
+    }
+}
+
+/// Ways an engine can break the rules or the protocol. All but unsolicited
+/// output forfeit the game.
+pub enum Violation {
+    /// A move that is not legal in the position, in long algebraic notation
+    Illegal(String),
+    /// `bestmove 0000` in a position that still has legal moves
+    NullMove,
+    /// A line that could not be parsed
+    Malformed(String),
+    /// Search output while no search was running
+    Unsolicited(String),
+    /// Search output before the `uci` handshake was done
+    Premature(String),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::Illegal(mv) => write!(f, "illegal move {mv}"),
+            Violation::NullMove => write!(f, "null move in a non-terminal position"),
+            Violation::Malformed(line) => write!(f, "malformed output: {line}"),
+            Violation::Unsolicited(line) => write!(f, "unsolicited output: {line}"),
+            Violation::Premature(line) => write!(f, "output before `uciok': {line}"),
+        }
+    }
+}
+
+pub enum Reply {
+    /// The move, with the last search information sent before it
+    Move(BestMove, Option<SearchInfo>),
+    Timeout,
+    Violation(Violation),
+    /// The engine stopped talking, with the reason given by the connection
+    Crashed(String),
+}
+
 async fn query_best_move(
     engine: &mut EngineHandle,
-    game: &GameState,
+    log: &mut UciLog,
+    mut arg: VecDeque<UciGui>,
     time: Duration,
     timeout: Duration,
-) -> tokio::io::Result<Option<BestMove>> {
-    let mut arg = deque![
-        UciGui::Position(game.uci_position(), game.uci_line()),
-        UciGui::Go(GoCommand::Infinite()),
-    ];
-
+) -> tokio::io::Result<Reply> {
     let now = Instant::now();
+    let mut info: Option<SearchInfo> = None;
 
     let (mut ingress, mut egress) = engine.split();
 
     loop {
         select! {
             _ = sleep(time / 10) => {}
-            Ok(uci) = ingress.receive() => {
-                if let UciEngine::BestMove(bm) = uci {
-                    return Ok(Some(bm));
+            uci = ingress.receive() => {
+                if let Ok(msg) = &uci {
+                    log.received(msg).await?;
+                }
+                match uci {
+                    Ok(UciEngine::BestMove(bm)) => return Ok(Reply::Move(bm, info)),
+                    Ok(other) => {
+                        if let Some(later) = SearchInfo::from_uci(&other) {
+                            match &mut info {
+                                Some(info) => info.update(later),
+                                None => info = Some(later),
+                            }
+                        }
+                    }
+                    Err(e) if e.kind() == tokio::io::ErrorKind::InvalidData => {
+                        return Ok(Reply::Violation(Violation::Malformed(e.to_string())));
+                    }
+                    Err(e) => return Ok(Reply::Crashed(e.to_string())),
                 }
             }
-            _ = egress.send(arg.front()), if !arg.is_empty() => {
-                arg.pop_front();
+            sent = egress.send(arg.front()), if !arg.is_empty() => {
+                if let Err(e) = sent {
+                    return Ok(Reply::Crashed(e.to_string()));
+                }
+                if let Some(cmd) = arg.pop_front() {
+                    log.sent(&cmd).await?;
+                }
             }
         }
 
@@ -194,7 +944,7 @@ async fn query_best_move(
         }
 
         if now.elapsed() > timeout * 2 {
-            return Ok(None);
+            return Ok(Reply::Timeout);
         }
     }
 }
@@ -0,0 +1,458 @@
+use std::{fmt::Display, path::Path, sync::OnceLock};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// Commands that can be bound to keys in the TUI modes. Each mode looks
+/// keys up among its own actions, so modes may bind a key differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    PrevMove,
+    NextMove,
+    FirstMove,
+    LastMove,
+    PrevGame,
+    NextGame,
+    Rotate,
+    Frame,
+    ClearAnnotations,
+    WriteAnnotated,
+    Log,
+    LogBack,
+    LogForward,
+    Decrease,
+    Increase,
+    DecreaseMore,
+    IncreaseMore,
+    Activate,
+    RemoveOption,
+    Preview,
+    WriteProfile,
+    Help,
+    Suspend,
+    Quit,
+}
+
+impl Action {
+    pub fn description(self) -> &'static str {
+        use Action::*;
+        match self {
+            PrevMove => "previous move",
+            NextMove => "next move",
+            FirstMove => "first move",
+            LastMove => "last move",
+            PrevGame => "previous game",
+            NextGame => "next game",
+            Rotate => "rotate board",
+            Frame => "toggle coordinate frame",
+            ClearAnnotations => "clear annotations",
+            WriteAnnotated => "write annotated PGN",
+            Log => "toggle UCI log",
+            LogBack => "scroll UCI log back",
+            LogForward => "scroll UCI log forward",
+            Decrease => "lower spin, previous combo value",
+            Increase => "raise spin, next combo value",
+            DecreaseMore => "lower spin in larger steps",
+            IncreaseMore => "raise spin in larger steps",
+            Activate => "toggle check, type value",
+            RemoveOption => "remove option from profile",
+            Preview => "toggle TOML preview",
+            WriteProfile => "write profile",
+            Help => "toggle this help",
+            Suspend => "suspend to shell",
+            Quit => "exit",
+        }
+    }
+}
+
+/// A key with modifiers, written like `Ctrl+Left`, `Alt+<` or `x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        // the shift state of characters is already part of the character
+        let relevant = |m: KeyModifiers| match key.code {
+            KeyCode::Char(_) => m - KeyModifiers::SHIFT,
+            _ => m,
+        };
+
+        self.code == key.code && relevant(self.modifiers) == relevant(key.modifiers)
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::str::FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+
+        // a lone `+` is a key, not a separator
+        while let Some((m, r)) = rest.split_once('+').filter(|(_, r)| !r.is_empty()) {
+            modifiers |= match &m.to_lowercase()[..] {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier `{m}' in key `{s}'")),
+            };
+            rest = r;
+        }
+
+        let code = match &rest.to_lowercase()[..] {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "space" => KeyCode::Char(' '),
+            f if f.len() > 1 && f.starts_with('f') => match f[1..].parse() {
+                Ok(n) => KeyCode::F(n),
+                Err(_) => return Err(format!("Unknown key `{s}'")),
+            },
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("Unknown key `{s}'")),
+                }
+            }
+        };
+
+        Ok(KeyBinding { code, modifiers })
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(value: KeyBinding) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vi,
+    Emacs,
+}
+
+impl Preset {
+    pub fn bindings(self) -> IndexMap<Action, Vec<KeyBinding>> {
+        use Action::*;
+
+        let table: &[(Action, &[&str])] = match self {
+            Preset::Default => &[
+                (PrevMove, &["Up"]),
+                (NextMove, &["Down"]),
+                (FirstMove, &["Home"]),
+                (LastMove, &["End"]),
+                (PrevGame, &["Ctrl+Left"]),
+                (NextGame, &["Ctrl+Right"]),
+                (Rotate, &["x"]),
+                (Frame, &["F"]),
+                (ClearAnnotations, &["Delete"]),
+                (WriteAnnotated, &["W"]),
+                (Log, &["U"]),
+                (LogBack, &["PageUp"]),
+                (LogForward, &["PageDown"]),
+                (Decrease, &["Left"]),
+                (Increase, &["Right"]),
+                (DecreaseMore, &["Shift+Left"]),
+                (IncreaseMore, &["Shift+Right"]),
+                (Activate, &["Enter", "Space"]),
+                (RemoveOption, &["Delete"]),
+                (Preview, &["Tab"]),
+                (WriteProfile, &["Ctrl+s"]),
+                (Help, &["?"]),
+                (Suspend, &["Ctrl+z"]),
+                (Quit, &["Esc", "Ctrl+c"]),
+            ],
+            // move entry takes the file letters, so vi motions that collide
+            // with them are replaced by their line-motion counterparts
+            Preset::Vi => &[
+                (PrevMove, &["k", "Up"]),
+                (NextMove, &["j", "Down"]),
+                (FirstMove, &["^"]),
+                (LastMove, &["$"]),
+                (PrevGame, &["H"]),
+                (NextGame, &["L"]),
+                (Rotate, &["x"]),
+                (Frame, &["F"]),
+                (ClearAnnotations, &["X"]),
+                (WriteAnnotated, &["W"]),
+                (Log, &["U"]),
+                (LogBack, &["Ctrl+u", "PageUp"]),
+                (LogForward, &["Ctrl+d", "PageDown"]),
+                (Decrease, &["h", "Left"]),
+                (Increase, &["l", "Right"]),
+                (DecreaseMore, &["H", "Shift+Left"]),
+                (IncreaseMore, &["L", "Shift+Right"]),
+                (Activate, &["Enter", "Space"]),
+                (RemoveOption, &["x", "Delete"]),
+                (Preview, &["Tab"]),
+                (WriteProfile, &["W", "Ctrl+s"]),
+                (Help, &["?"]),
+                (Suspend, &["Ctrl+z"]),
+                (Quit, &["q", "Ctrl+c"]),
+            ],
+            Preset::Emacs => &[
+                (PrevMove, &["Ctrl+p", "Up"]),
+                (NextMove, &["Ctrl+n", "Down"]),
+                (FirstMove, &["Alt+<"]),
+                (LastMove, &["Alt+>"]),
+                (PrevGame, &["Alt+p"]),
+                (NextGame, &["Alt+n"]),
+                (Rotate, &["Ctrl+t"]),
+                (Frame, &["Alt+f"]),
+                (ClearAnnotations, &["Ctrl+k"]),
+                (WriteAnnotated, &["Ctrl+s"]),
+                (Log, &["Alt+l"]),
+                (LogBack, &["Alt+v", "PageUp"]),
+                (LogForward, &["Ctrl+v", "PageDown"]),
+                (Decrease, &["Ctrl+b", "Left"]),
+                (Increase, &["Ctrl+f", "Right"]),
+                (DecreaseMore, &["Alt+b", "Shift+Left"]),
+                (IncreaseMore, &["Alt+f", "Shift+Right"]),
+                (Activate, &["Enter", "Space"]),
+                (RemoveOption, &["Ctrl+k", "Delete"]),
+                (Preview, &["Tab"]),
+                (WriteProfile, &["Ctrl+s"]),
+                // Ctrl+h arrives as Backspace in many terminals
+                (Help, &["F1", "?"]),
+                (Suspend, &["Ctrl+z"]),
+                (Quit, &["Ctrl+g", "Ctrl+c"]),
+            ],
+        };
+
+        table
+            .iter()
+            .map(|(a, ks)| (*a, ks.iter().map(|k| k.parse().unwrap()).collect()))
+            .collect()
+    }
+}
+
+/// Key bindings shared by all TUI modes: a preset, with individual actions
+/// rebound by the `[bindings]` table. The preset is resolved when the keymap
+/// is built, so `bindings` holds the keys of every action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "KeymapFile")]
+pub struct Keymap {
+    pub preset: Preset,
+    pub bindings: IndexMap<Action, Vec<KeyBinding>>,
+}
+
+/// A keymap as written in its file.
+#[derive(Debug, Default, Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    preset: Preset,
+    #[serde(default)]
+    bindings: IndexMap<Action, Vec<KeyBinding>>,
+}
+
+impl From<KeymapFile> for Keymap {
+    fn from(file: KeymapFile) -> Self {
+        let mut bindings = file.preset.bindings();
+        bindings.extend(file.bindings);
+        Keymap {
+            preset: file.preset,
+            bindings,
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        KeymapFile::default().into()
+    }
+}
+
+static KEYMAP: OnceLock<Keymap> = OnceLock::new();
+
+impl Keymap {
+    pub async fn load(path: &Path) -> tokio::io::Result<Self> {
+        let data = tokio::fs::read(path).await?;
+        toml::from_slice(&data)
+            .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Sets the keymap used by [`Keymap::active`]. Only the first call has
+    /// an effect.
+    pub fn install(self) {
+        let _ = KEYMAP.set(self);
+    }
+
+    pub fn active() -> &'static Keymap {
+        KEYMAP.get_or_init(Keymap::default)
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The first of the mode's actions bound to the key.
+    pub fn action(&self, key: &KeyEvent, actions: &[Action]) -> Option<Action> {
+        actions
+            .iter()
+            .copied()
+            .find(|a| self.keys(*a).iter().any(|k| k.matches(key)))
+    }
+
+    pub fn describe(&self, action: Action) -> String {
+        self.keys(action)
+            .iter()
+            .map(|k| format!("[{k}]"))
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+
+    /// Help text for the given actions, one per line.
+    pub fn help(&self, actions: &[Action]) -> String {
+        actions
+            .iter()
+            .map(|a| format!("{:<24}{}", self.describe(*a), a.description()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::PositionAnalyzer, analyze::GameReviewer, edit_profile::ProfileEditor};
+
+    fn key(s: &str) -> KeyBinding {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        assert_eq!(
+            key("Ctrl+Left"),
+            KeyBinding {
+                code: KeyCode::Left,
+                modifiers: KeyModifiers::CONTROL
+            }
+        );
+        assert_eq!(
+            key("control+alt+x"),
+            KeyBinding {
+                code: KeyCode::Char('x'),
+                modifiers: KeyModifiers::CONTROL | KeyModifiers::ALT
+            }
+        );
+        assert_eq!(key("Meta+<"), key("Alt+<"));
+    }
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(key("+").code, KeyCode::Char('+'));
+        assert_eq!(key("Ctrl++").code, KeyCode::Char('+'));
+        assert_eq!(key("Space").code, KeyCode::Char(' '));
+        assert_eq!(key("F").code, KeyCode::Char('F'));
+        assert_eq!(key("F12").code, KeyCode::F(12));
+        assert_eq!(key("del").code, KeyCode::Delete);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("Hyper+x".parse::<KeyBinding>().is_err());
+        assert!("Fx".parse::<KeyBinding>().is_err());
+        assert!("xy".parse::<KeyBinding>().is_err());
+        assert!("".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in ["Ctrl+Left", "Alt+<", "Space", "F1", "PageDown", "x", "Esc"] {
+            assert_eq!(key(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn presets_bind_every_action_without_conflicts() {
+        let modes: [&[Action]; 3] =
+            [&GameReviewer::ACTIONS, &PositionAnalyzer::ACTIONS, &ProfileEditor::ACTIONS];
+        for preset in [Preset::Default, Preset::Vi, Preset::Emacs] {
+            let keymap = Keymap::from(KeymapFile {
+                preset,
+                ..Default::default()
+            });
+            for actions in modes {
+                for action in actions {
+                    let keys = keymap.keys(*action);
+                    assert!(!keys.is_empty(), "{preset:?} leaves {action:?} unbound");
+                    for k in keys {
+                        let event = KeyEvent::new(k.code, k.modifiers);
+                        assert_eq!(
+                            keymap.action(&event, actions),
+                            Some(*action),
+                            "{preset:?} {k}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bindings_override_the_preset() {
+        let keymap: Keymap = toml::from_str(
+            r#"
+            preset = "vi"
+            [bindings]
+            quit = ["Ctrl+q"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(keymap.keys(Action::Quit), [key("Ctrl+q")]);
+        assert_eq!(keymap.keys(Action::NextMove), [key("j"), key("Down")]);
+    }
+}
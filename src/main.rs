@@ -28,7 +28,7 @@ use mintymacks::{
 use std::{
     collections::{VecDeque, vec_deque},
     io::{Result, Write, stdout},
-    path::Path,
+    path::{Path, PathBuf},
     process::{ExitCode, Output, exit},
     time::Duration,
 };
@@ -36,15 +36,41 @@ use tokio::{io::AsyncWriteExt, select, time::sleep};
 use tokio_stream::{Stream, StreamExt};
 
 use crate::{
+    analysis::AnalyzePosition,
     analyze::ReviewGame,
+    check_engine::CheckEngine,
+    edit_profile::EditProfile,
+    epd::EpdSuite,
     faceoff::Faceoff,
+    keymap::Keymap,
     new_profile::{NewBot, NewCommand, ProfileCommand},
+    perft::Perft,
+    ratings::Ratings,
+    registry::ProfilesCommand,
+    sweep::Sweep,
+    validate_profile::ValidateProfile,
 };
 
+mod adjudicate;
+mod analysis;
 mod analyze;
+mod check_engine;
+mod edit_profile;
+mod elo;
+mod epd;
 mod faceoff;
+mod keymap;
+mod match_state;
 mod move_select;
 mod new_profile;
+mod perft;
+mod ratings;
+mod registry;
+mod results;
+mod search_info;
+mod sweep;
+mod uci_log;
+mod validate_profile;
 mod widgets;
 
 pub trait Runnable {
@@ -55,26 +81,97 @@ pub trait Runnable {
 pub struct Command {
     #[clap(subcommand)]
     subcommand: SubCommand,
+
+    /// Keymap for the interactive modes [default: keymap.toml in the config directory]
+    #[clap(long, global = true)]
+    keymap: Option<PathBuf>,
+
+    /// Directory to look up profiles by name in, may be repeated [default: profiles in the config directory]
+    #[clap(long, global = true)]
+    profile_dir: Vec<PathBuf>,
+}
+
+/// `$XDG_CONFIG_HOME/mintymacks`, falling back to `~/.config/mintymacks`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(base.join("mintymacks"))
 }
 
 impl Runnable for Command {
     async fn run(self) -> tokio::io::Result<()> {
+        // a broken keymap should not stop commands that never read keys
+        if self.subcommand.interactive() {
+            match self.keymap {
+                Some(path) => Keymap::load(&path).await?.install(),
+                None => {
+                    if let Some(path) = config_dir().map(|d| d.join("keymap.toml")) {
+                        if path.exists() {
+                            Keymap::load(&path).await?.install();
+                        }
+                    }
+                }
+            }
+        }
+
+        if !self.profile_dir.is_empty() {
+            registry::install_dirs(self.profile_dir);
+        }
+
         match self.subcommand {
             SubCommand::New(np) => np.run().await,
             SubCommand::Fight(faceoff) => faceoff.run().await,
             SubCommand::Review(analyze_game) => analyze_game.run().await,
+            SubCommand::Analyze(analyze_position) => analyze_position.run().await,
+            SubCommand::CheckEngine(check_engine) => check_engine.run().await,
+            SubCommand::Perft(perft) => perft.run().await,
+            SubCommand::Epd(epd) => epd.run().await,
+            SubCommand::Edit(edit_profile) => edit_profile.run().await,
+            SubCommand::Validate(validate) => validate.run().await,
+            SubCommand::Profiles(profiles) => profiles.run().await,
+            SubCommand::Sweep(sweep) => sweep.run().await,
+            SubCommand::Ratings(ratings) => ratings.run().await,
         }
     }
 }
 
 #[derive(Subcommand)]
 pub enum SubCommand {
-    /// Creates a new profile and writes to STDOUT
+    /// Creates a new profile in the profile directory
     New(NewCommand),
     /// Faces two chessbots off against each other
     Fight(Faceoff),
     /// Review a game from a PGN file
     Review(ReviewGame),
+    /// Shows an engine's best lines for a position
+    Analyze(AnalyzePosition),
+    /// Runs UCI protocol scenarios against a bot and reports which pass
+    CheckEngine(CheckEngine),
+    /// Counts move generator leaf nodes, optionally against an engine
+    Perft(Perft),
+    /// Scores a bot on a suite of EPD test positions
+    Epd(EpdSuite),
+    /// Edits the engine options of a bot profile
+    Edit(EditProfile),
+    /// Checks a bot profile's options against what the engine accepts
+    Validate(ValidateProfile),
+    /// Lists, shows and removes profiles in the profile directories
+    Profiles(ProfilesCommand),
+    /// Plays settings of a bot's options against a reference bot and estimates their Elo
+    Sweep(Sweep),
+    /// Rates bots from the games recorded by fight and sweep
+    Ratings(Ratings),
+}
+
+impl SubCommand {
+    /// Whether the subcommand runs a TUI and so needs the keymap.
+    pub fn interactive(&self) -> bool {
+        matches!(
+            self,
+            SubCommand::Review(_) | SubCommand::Analyze(_) | SubCommand::Edit(_)
+        )
+    }
 }
 
 #[tokio::main]
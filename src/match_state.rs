@@ -0,0 +1,159 @@
+use std::path::Path;
+
+use mintymacks::profile::EngineProfile;
+use serde::{Deserialize, Serialize};
+
+use crate::adjudicate::AdjudicationRules;
+
+/// Progress of a match, written after every game so an interrupted match
+/// can be resumed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchState {
+    /// Turn time in miliseconds
+    pub time: u64,
+    /// Turn timeout in miliseconds
+    pub timeout: u64,
+    /// Number of games in the match
+    pub games: usize,
+    pub adjudication: AdjudicationRules,
+    /// Scores of the first and second profile
+    pub scores: [f64; 2],
+    pub crashes: [usize; 2],
+    /// PGN of every finished game, in order
+    pub played: Vec<String>,
+    /// Both profiles with inheritance and option overlays resolved
+    pub profiles: [toml::Value; 2],
+}
+
+impl MatchState {
+    pub fn new(
+        time: u64,
+        timeout: u64,
+        games: usize,
+        adjudication: AdjudicationRules,
+        first: &EngineProfile,
+        second: &EngineProfile,
+    ) -> tokio::io::Result<Self> {
+        Ok(MatchState {
+            time,
+            timeout,
+            games,
+            adjudication,
+            scores: [0.0; 2],
+            crashes: [0; 2],
+            played: vec![],
+            profiles: [profile_value(first)?, profile_value(second)?],
+        })
+    }
+
+    pub async fn load(path: &Path) -> tokio::io::Result<Self> {
+        let data = tokio::fs::read(path).await?;
+        toml::from_slice(&data)
+            .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Writes the state next to the file and moves it over, so a match
+    /// killed while writing keeps the previous state.
+    pub async fn write(&self, path: &Path) -> tokio::io::Result<()> {
+        let text = toml::to_string(self)
+            .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e.to_string()))?;
+        let temporary = path.with_extension("tmp");
+        tokio::fs::write(&temporary, text).await?;
+        tokio::fs::rename(&temporary, path).await
+    }
+
+    /// Why a match with these settings cannot continue from this state, if
+    /// it cannot. The schedule and adjudication rules are not compared, as
+    /// a resumed match takes them from the state.
+    pub fn mismatch(&self, other: &MatchState) -> Option<String> {
+        if self.profiles[0] != other.profiles[0] {
+            return Some(String::from("The white profile has changed"));
+        }
+        if self.profiles[1] != other.profiles[1] {
+            return Some(String::from("The black profile has changed"));
+        }
+        if (self.time, self.timeout) != (other.time, other.timeout) {
+            return Some(format!(
+                "The match was played with --time {} --timeout {}",
+                self.time, self.timeout
+            ));
+        }
+        None
+    }
+}
+
+fn profile_value(profile: &EngineProfile) -> tokio::io::Result<toml::Value> {
+    toml::Value::try_from(profile)
+        .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, threads: i64) -> EngineProfile {
+        toml::from_str(&format!(
+            "[engine]\nname = \"{name}\"\nauthor = \"\"\ncommand = [\"{name}\", []]\nlog = false\n\n[options]\nThreads = {threads}\n"
+        ))
+        .unwrap()
+    }
+
+    fn state(time: u64, white: &EngineProfile, black: &EngineProfile) -> MatchState {
+        MatchState::new(
+            time,
+            2 * time,
+            10,
+            AdjudicationRules::default(),
+            white,
+            black,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn same_settings_match() {
+        let (white, black) = (profile("a", 1), profile("b", 1));
+        let mut saved = state(100, &white, &black);
+        saved.played.push(String::from("1. e4 *"));
+        saved.scores = [1.0, 0.0];
+
+        assert_eq!(saved.mismatch(&state(100, &white, &black)), None);
+    }
+
+    #[test]
+    fn changed_profiles_and_times_mismatch() {
+        let (white, black) = (profile("a", 1), profile("b", 1));
+        let saved = state(100, &white, &black);
+
+        assert!(
+            saved
+                .mismatch(&state(100, &profile("a", 2), &black))
+                .unwrap()
+                .contains("white")
+        );
+        assert!(
+            saved
+                .mismatch(&state(100, &white, &profile("c", 1)))
+                .unwrap()
+                .contains("black")
+        );
+        assert!(
+            saved
+                .mismatch(&state(200, &white, &black))
+                .unwrap()
+                .contains("--time 100")
+        );
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut saved = state(100, &profile("a", 1), &profile("b", 1));
+        saved.adjudication.draw_score = Some(10);
+        saved
+            .played
+            .push(String::from("[Result \"1-0\"]\n\n1. e4 1-0"));
+
+        let text = toml::to_string(&saved).unwrap();
+        assert_eq!(toml::from_str::<MatchState>(&text).unwrap(), saved);
+    }
+}
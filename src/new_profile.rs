@@ -1,11 +1,12 @@
 use std::{
     collections::{HashMap, VecDeque},
+    io::IsTerminal,
     path::{Path, PathBuf},
     process::{ExitCode, exit},
     time::Duration,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use indexmap::IndexMap;
 use mintymacks::{
     engine::{EngineDetails, EngineHandle},
@@ -26,7 +27,7 @@ use tokio::{
     time::sleep,
 };
 
-use crate::Runnable;
+use crate::{Runnable, config_dir, registry};
 
 #[derive(Parser)]
 pub struct NewCommand {
@@ -43,31 +44,115 @@ impl Runnable for NewCommand {
     }
 }
 
-impl NewCommand {
-    pub async fn create_profile(name: &str, res: String) -> tokio::io::Result<()> {
-        let filename = name.to_lowercase().replace(" ", "-") + ".toml";
-
-        if let Ok(file) = File::create_new(&filename).await {
-            let mut file = BufWriter::new(file);
+/// Where `new` puts the profile it creates.
+#[derive(Args, Clone, Debug, Default)]
+pub struct ProfileOutput {
+    /// Profile file to write, instead of one named after the player or bot
+    /// in the profile directory
+    #[clap(long, conflicts_with = "stdout")]
+    pub output: Option<PathBuf>,
 
-            file.write_all(&res[..].as_bytes()).await?;
-            file.flush().await?;
+    /// Write the profile to STDOUT instead of a file
+    #[clap(long)]
+    pub stdout: bool,
 
-            stderr()
-                .write_all(format!("Profile created: {filename}\n").as_bytes())
-                .await?;
-            stderr().flush().await?;
+    /// Overwrite an existing profile
+    #[clap(long)]
+    pub force: bool,
+}
 
+impl NewCommand {
+    pub async fn create_profile(
+        name: &str,
+        res: String,
+        output: &ProfileOutput,
+    ) -> tokio::io::Result<()> {
+        if output.stdout {
+            stdout().write_all(res.as_bytes()).await?;
+            stdout().flush().await?;
             ExitCode::SUCCESS.exit_process();
+        }
+
+        let path = match &output.output {
+            Some(path) => path.clone(),
+            None => {
+                let dir = registry::dirs()
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| PathBuf::from("."));
+                tokio::fs::create_dir_all(&dir).await?;
+                dir.join(profile_file_name(name))
+            }
+        };
+        let filename = path.to_string_lossy();
+
+        let file = if output.force {
+            File::create(&path).await
         } else {
-            stderr()
-                .write_all(format!("Profile already exists: {filename}\n").as_bytes())
-                .await?;
-            stderr().flush().await?;
+            File::create_new(&path).await
+        };
 
-            ExitCode::FAILURE.exit_process();
+        match file {
+            Ok(file) => {
+                let mut file = BufWriter::new(file);
+
+                file.write_all(&res[..].as_bytes()).await?;
+                file.flush().await?;
+
+                stderr()
+                    .write_all(format!("Profile created: {filename}\n").as_bytes())
+                    .await?;
+                stderr().flush().await?;
+
+                ExitCode::SUCCESS.exit_process();
+            }
+            Err(e) if e.kind() == tokio::io::ErrorKind::AlreadyExists => {
+                stderr()
+                    .write_all(
+                        format!("Profile already exists: {filename}, use --force to overwrite\n")
+                            .as_bytes(),
+                    )
+                    .await?;
+                stderr().flush().await?;
+
+                ExitCode::FAILURE.exit_process();
+            }
+            Err(e) => {
+                stderr()
+                    .write_all(format!("Unable to create {filename}: {e}\n").as_bytes())
+                    .await?;
+                stderr().flush().await?;
+
+                ExitCode::FAILURE.exit_process();
+            }
+        }
+    }
+}
+
+/// A file name for a profile, keeping letters, digits, `_` and `.` and
+/// turning runs of anything else, slashes included, into single dashes.
+pub fn profile_file_name(name: &str) -> String {
+    let mut res = String::new();
+    for c in name.to_lowercase().chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            res.push(c);
+        } else if !res.ends_with('-') {
+            res.push('-');
         }
     }
+
+    let res = res.trim_matches(['-', '.']);
+    if res.is_empty() {
+        String::from("profile.toml")
+    } else {
+        format!("{res}.toml")
+    }
+}
+
+/// `profiles` in the config directory, the default place `new` writes
+/// profiles to and the registry looks them up in.
+pub fn profile_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("profiles"))
 }
 
 #[derive(Subcommand)]
@@ -79,50 +164,172 @@ pub enum ProfileCommand {
     Bot(NewBot),
 }
 
+/// FIDE and national titles.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[value(rename_all = "UPPER")]
+pub enum Title {
+    GM,
+    IM,
+    FM,
+    CM,
+    WGM,
+    WIM,
+    WFM,
+    WCM,
+    NM,
+    #[default]
+    #[value(name = "none")]
+    None,
+}
+
+impl Title {
+    /// The title as stored in the profile, empty for none.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Title::None => "",
+            title => title.to_possible_value().unwrap().get_name(),
+        }
+    }
+}
+
 #[derive(Parser)]
-pub struct NewPlayer;
+pub struct NewPlayer {
+    /// Player name, asked for if not given
+    #[clap(long)]
+    pub name: Option<String>,
 
-impl Runnable for NewPlayer {
-    async fn run(self) -> tokio::io::Result<()> {
-        let mut cin = BufReader::new(stdin());
+    /// Title, asked for if not given
+    #[clap(long, ignore_case = true)]
+    pub title: Option<Title>,
 
-        stderr().write_all(b"Name: ").await?;
-        stderr().flush().await?;
-        let mut name = String::new();
-        cin.read_line(&mut name).await?;
-        name = name.trim().to_string();
+    /// FIDE Elo, asked for if not given, 0 for unrated
+    #[clap(long, value_parser = clap::value_parser!(i32).range(0..))]
+    pub elo: Option<i32>,
+
+    /// FIDE federation, like NOR. Informational only: it is written as a
+    /// comment next to the Elo, and nothing reads it back
+    #[clap(long)]
+    pub federation: Option<String>,
+
+    #[clap(flatten)]
+    pub output: ProfileOutput,
+}
 
-        stderr().write_all(b"FIDE Title (CM, FM, IM, GM): ").await?;
+/// Asks on stderr until the answer parses, or fails if stdin is not a
+/// terminal, since scripts are expected to pass flags instead.
+async fn ask<T>(
+    cin: &mut BufReader<tokio::io::Stdin>,
+    prompt: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> tokio::io::Result<T> {
+    if !std::io::stdin().is_terminal() {
+        stderr()
+            .write_all(format!("Missing value for: {prompt}\n").as_bytes())
+            .await?;
+        ExitCode::FAILURE.exit_process();
+    }
+
+    loop {
+        stderr().write_all(format!("{prompt}: ").as_bytes()).await?;
         stderr().flush().await?;
-        let mut title = String::new();
-        cin.read_line(&mut title).await?;
-        title = title.trim().to_string();
 
-        match &title[..] {
-            "CM" | "FM" | "IM" | "GM" => title.clear(),
-            _ => title.clear(),
+        let mut answer = String::new();
+        if cin.read_line(&mut answer).await? == 0 {
+            ExitCode::FAILURE.exit_process();
         }
 
-        stderr().write_all(b"FIDE ELO: ").await?;
-        stderr().flush().await?;
-        let mut elo = String::new();
-        cin.read_line(&mut elo).await?;
-        elo = elo.trim().to_string();
+        match parse(answer.trim()) {
+            Ok(res) => return Ok(res),
+            Err(e) => stderr().write_all(format!("{e}\n").as_bytes()).await?,
+        }
+    }
+}
+
+impl Runnable for NewPlayer {
+    async fn run(self) -> tokio::io::Result<()> {
+        let mut cin = BufReader::new(stdin());
 
-        let elo = i32::from_str_radix(&elo, 10).unwrap_or(0);
-        let res = toml::to_string(&Profile::Player(PlayerProfile {
+        let name = match self.name {
+            Some(name) => name,
+            None => {
+                ask(&mut cin, "Name", |s| match s {
+                    "" => Err(String::from("The name cannot be empty")),
+                    s => Ok(s.to_string()),
+                })
+                .await?
+            }
+        };
+
+        let title = match self.title {
+            Some(title) => title,
+            None => {
+                ask(
+                    &mut cin,
+                    "Title (GM, IM, FM, CM, WGM, WIM, WFM, WCM, NM or none)",
+                    |s| match s {
+                        "" => Ok(Title::None),
+                        s => Title::from_str(s, true).map_err(|_| format!("Unknown title: {s}")),
+                    },
+                )
+                .await?
+            }
+        };
+
+        let elo = match self.elo {
+            Some(elo) => elo,
+            None => {
+                ask(&mut cin, "FIDE Elo (0 for unrated)", |s| match s {
+                    "" => Ok(0),
+                    s => match s.parse::<i32>() {
+                        Ok(elo) if elo >= 0 => Ok(elo),
+                        Ok(_) => Err(String::from("The Elo cannot be negative")),
+                        Err(_) => Err(format!("Not a number: {s}")),
+                    },
+                })
+                .await?
+            }
+        };
+
+        let mut res = toml::to_string(&Profile::Player(PlayerProfile {
             human: PlayerMetadata {
                 name: name.clone(),
-                title,
+                title: title.as_str().to_string(),
                 elo,
             },
         }))
         .expect("Unable to render TOML");
 
-        NewCommand::create_profile(&name, res).await
+        if let Some(federation) = &self.federation {
+            res = with_federation(&res, federation);
+        }
+
+        NewCommand::create_profile(&name, res, &self.output).await
     }
 }
 
+/// Notes the federation in a comment after the Elo, since the profile
+/// format has no field for it.
+fn with_federation(profile: &str, federation: &str) -> String {
+    fn find(table: &mut toml_edit::Table) -> Option<&mut toml_edit::Value> {
+        if table.contains_key("elo") {
+            return table.get_mut("elo")?.as_value_mut();
+        }
+        table
+            .iter_mut()
+            .filter_map(|(_, item)| item.as_table_mut())
+            .find_map(find)
+    }
+
+    let Ok(mut doc) = profile.parse::<toml_edit::DocumentMut>() else {
+        return profile.to_string();
+    };
+    if let Some(elo) = find(doc.as_table_mut()) {
+        elo.decor_mut()
+            .set_suffix(format!(" # federation {}", federation.to_uppercase()));
+    }
+    doc.to_string()
+}
+
 #[derive(Parser)]
 pub struct NewBot {
     /// Path to the bot executable
@@ -131,6 +338,13 @@ pub struct NewBot {
     /// Additional arguments
     #[clap(last = true)]
     pub args: Vec<String>,
+
+    /// Have `fight` log the UCI traffic of the bot
+    #[clap(long)]
+    pub log: bool,
+
+    #[clap(flatten)]
+    pub output: ProfileOutput,
 }
 
 impl Runnable for NewBot {
@@ -145,11 +359,11 @@ impl Runnable for NewBot {
             name: details.name.clone(),
             author: details.author.clone(),
             command: (self.bot, self.args),
-            log: false,
+            log: self.log,
         };
 
         let res = metadata.engine_profile_toml(&details.options);
 
-        NewCommand::create_profile(&metadata.name, res).await
+        NewCommand::create_profile(&metadata.name, res, &self.output).await
     }
 }
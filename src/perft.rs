@@ -0,0 +1,399 @@
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, builder::RangedU64ValueParser};
+use indexmap::IndexMap;
+use mintymacks::{
+    bits::board::BitBoard,
+    deque,
+    model::moves::ChessMove,
+    notation::{
+        fen::render_fen,
+        uci::gui::{GoCommand, UciGui},
+    },
+    utils::{eprintln_async, println_async},
+};
+use tokio::time::Instant;
+
+use crate::{
+    Runnable,
+    check_engine::{Outcome, Probe, test_position},
+    faceoff::read_profile,
+    widgets::{
+        move_input::lan,
+        move_select::{game_from_fen, legal_moves},
+    },
+};
+
+pub const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Positions with known node counts, by depth from 1.
+pub const SUITE: [(&str, &str, &[u64]); 6] = [
+    (
+        "start position",
+        STARTPOS,
+        &[20, 400, 8902, 197281, 4865609, 119060324],
+    ),
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        &[48, 2039, 97862, 4085603, 193690690],
+    ),
+    (
+        "rook endgame",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        &[14, 191, 2812, 43238, 674624, 11030083],
+    ),
+    (
+        "promotions",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        &[6, 264, 9467, 422333, 15833292],
+    ),
+    (
+        "discovered checks",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        &[44, 1486, 62379, 2103487, 89941194],
+    ),
+    (
+        "middlegame",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        &[46, 2079, 89890, 3894594, 164075551],
+    ),
+];
+
+#[derive(Parser)]
+pub struct Perft {
+    /// Position to count from
+    #[clap(long, default_value = STARTPOS)]
+    pub fen: String,
+
+    /// Depth in plies
+    #[clap(long, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub depth: usize,
+
+    /// Run the built-in positions with known counts instead, up to the depth
+    #[clap(long, conflicts_with = "fen")]
+    pub suite: bool,
+
+    /// Bot profile of an engine supporting `go perft` to compare root moves with
+    #[clap(long)]
+    pub engine: Option<PathBuf>,
+
+    /// Follow a mismatching root move down to the position where the move
+    /// lists differ
+    #[clap(long, requires = "engine")]
+    pub drill: bool,
+}
+
+impl Runnable for Perft {
+    async fn run(self) -> tokio::io::Result<()> {
+        let mut probe = match &self.engine {
+            Some(path) => {
+                let profile = read_profile(path).await?;
+                let mut probe = Probe::spawn(&profile.engine.command).await?;
+                let handshake = probe.handshake().await?;
+                if handshake.outcome != Outcome::Pass {
+                    eprintln_async!("{handshake}").await;
+                    ExitCode::FAILURE.exit_process();
+                }
+                Some(probe)
+            }
+            None => None,
+        };
+
+        let positions = if self.suite {
+            SUITE
+                .iter()
+                .map(|(name, fen, counts)| (name.to_string(), fen.to_string(), Some(*counts)))
+                .collect()
+        } else {
+            vec![(self.fen.clone(), self.fen.clone(), None)]
+        };
+
+        let mut failed = false;
+        for (name, fen, known) in positions {
+            let board = match game_from_fen(&fen) {
+                Ok(game) => game.board,
+                Err(e) => {
+                    eprintln_async!("{e}").await;
+                    ExitCode::FAILURE.exit_process();
+                }
+            };
+
+            let depth = match known {
+                Some(counts) => self.depth.min(counts.len()),
+                None => self.depth,
+            };
+
+            let started = Instant::now();
+            let ours = divide(&board, depth);
+            let total = ours.values().sum::<u64>();
+            let elapsed = started.elapsed();
+
+            if !self.suite {
+                for (mv, count) in &ours {
+                    println_async!("{mv}: {count}").await;
+                }
+            }
+            println_async!(
+                "{name}, depth {depth}: {total} nodes in {:.2}s",
+                elapsed.as_secs_f64()
+            )
+            .await;
+
+            if let Some(counts) = known {
+                if counts[depth - 1] != total {
+                    println_async!("  expected {}", counts[depth - 1]).await;
+                    failed = true;
+                }
+            }
+
+            if let Some(probe) = &mut probe {
+                let mismatch = compare(probe, &board, &fen, &[], depth, &ours).await?;
+                if let Some(mismatch) = mismatch {
+                    failed = true;
+                    if self.drill {
+                        drill(probe, &fen, depth, mismatch).await?;
+                    }
+                }
+            }
+        }
+
+        if failed {
+            ExitCode::FAILURE.exit_process();
+        }
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+/// The position after a move, leaving the original as it was.
+pub fn play(board: &BitBoard, mv: ChessMove) -> BitBoard {
+    let mut res = board.clone();
+    res.apply(mv);
+    res
+}
+
+/// Leaf nodes at the given depth, counting the moves of the last ply in
+/// bulk instead of playing them.
+pub fn perft(board: &BitBoard, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = legal_moves(board);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    moves
+        .into_iter()
+        .map(|mv| perft(&play(board, mv), depth - 1))
+        .sum()
+}
+
+/// Node counts below each root move, keyed by the move in long algebraic
+/// notation as engines print them.
+pub fn divide(board: &BitBoard, depth: usize) -> IndexMap<String, u64> {
+    if depth == 0 {
+        return IndexMap::new();
+    }
+
+    legal_moves(board)
+        .into_iter()
+        .map(|mv| (lan(mv), perft(&play(board, mv), depth - 1)))
+        .collect()
+}
+
+/// Asks the engine for its `go perft` divide, returning `None` if it never
+/// finishes.
+pub async fn engine_divide(
+    probe: &mut Probe,
+    fen: &str,
+    moves: &[String],
+    depth: usize,
+) -> tokio::io::Result<Option<IndexMap<String, u64>>> {
+    let moves = moves.iter().map(String::as_str).collect::<Vec<_>>();
+    let game = test_position(fen, &moves)
+        .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidInput, e))?;
+
+    // divide lines are not UCI, so they are read as text
+    let exchange = probe
+        .interleave_lines(
+            deque![Probe::position(&game), UciGui::Go(GoCommand::Perft(depth as u64))],
+            |l| l.starts_with("Nodes searched"),
+            Duration::from_secs(600),
+        )
+        .await?;
+    if !exchange.done {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        exchange
+            .messages
+            .iter()
+            .filter_map(|l| {
+                // divide lines look like `e2e4: 20`, unlike the summary
+                let (mv, count) = l.split_once(": ")?;
+                if mv.contains(char::is_whitespace) {
+                    return None;
+                }
+                Some((mv.trim().to_string(), count.trim().parse().ok()?))
+            })
+            .collect(),
+    ))
+}
+
+/// A root move on which we and the engine disagree.
+pub enum Mismatch {
+    /// Both generate the move but count different nodes below it
+    Count(String),
+    /// Only we generate the move
+    Ours(String),
+    /// Only the engine generates the move
+    Theirs(String),
+}
+
+/// Prints the root moves on which we and the engine disagree, returning
+/// the first of them.
+pub async fn compare(
+    probe: &mut Probe,
+    board: &BitBoard,
+    fen: &str,
+    moves: &[String],
+    depth: usize,
+    ours: &IndexMap<String, u64>,
+) -> tokio::io::Result<Option<Mismatch>> {
+    let Some(theirs) = engine_divide(probe, fen, moves, depth).await? else {
+        eprintln_async!("Engine did not finish `go perft {depth}'").await;
+        ExitCode::FAILURE.exit_process();
+    };
+
+    let mut first = None;
+    for mv in ours
+        .keys()
+        .chain(theirs.keys().filter(|mv| !ours.contains_key(*mv)))
+    {
+        let (a, b) = (ours.get(mv), theirs.get(mv));
+        if a == b {
+            continue;
+        }
+
+        let show = |c: Option<&u64>| c.map(u64::to_string).unwrap_or_else(|| String::from("-"));
+        println_async!("  {mv}: ours {}, engine {}", show(a), show(b)).await;
+        first.get_or_insert_with(|| match (a, b) {
+            (Some(_), Some(_)) => Mismatch::Count(mv.clone()),
+            (Some(_), None) => Mismatch::Ours(mv.clone()),
+            _ => Mismatch::Theirs(mv.clone()),
+        });
+    }
+
+    if first.is_some() {
+        println_async!("  at {}", render_fen(board)).await;
+    }
+
+    Ok(first)
+}
+
+/// Descends along moves with differing counts until the engine and we
+/// disagree on the moves themselves.
+pub async fn drill(
+    probe: &mut Probe,
+    fen: &str,
+    depth: usize,
+    mismatch: Mismatch,
+) -> tokio::io::Result<()> {
+    let mut board = match game_from_fen(fen) {
+        Ok(game) => game.board,
+        Err(e) => {
+            eprintln_async!("{e}").await;
+            ExitCode::FAILURE.exit_process();
+        }
+    };
+
+    let mut moves: Vec<String> = vec![];
+    let mut mismatch = mismatch;
+    let mut depth = depth;
+    loop {
+        let (mv, who) = match mismatch {
+            Mismatch::Count(mv) => (mv, None),
+            Mismatch::Ours(mv) => (mv, Some("only we generate")),
+            Mismatch::Theirs(mv) => (mv, Some("only the engine generates")),
+        };
+        if let Some(who) = who {
+            println_async!(
+                "Difference found: {who} {mv} after `{}', at {}",
+                moves.join(" "),
+                render_fen(&board)
+            )
+            .await;
+            return Ok(());
+        }
+
+        let Some(m) = legal_moves(&board).into_iter().find(|m| lan(*m) == mv) else {
+            return Ok(());
+        };
+        board = play(&board, m);
+        moves.push(mv);
+
+        // there is no ply left to descend into
+        if depth <= 1 {
+            println_async!(
+                "Difference found: counts differ at the leaf after `{}', at {}",
+                moves.join(" "),
+                render_fen(&board)
+            )
+            .await;
+            return Ok(());
+        }
+        depth -= 1;
+
+        println_async!("After {}, depth {depth}:", moves.join(" ")).await;
+        let ours = divide(&board, depth);
+        match compare(probe, &board, fen, &moves, depth, &ours).await? {
+            Some(next) => mismatch = next,
+            None => {
+                println_async!("  no difference below {}", moves.join(" ")).await;
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suite_counts() {
+        for (name, fen, counts) in SUITE {
+            let board = game_from_fen(fen).unwrap().board;
+            for (depth, expected) in counts.iter().enumerate().take(3) {
+                assert_eq!(
+                    perft(&board, depth + 1),
+                    *expected,
+                    "{name}, depth {}",
+                    depth + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        for (name, fen, counts) in SUITE {
+            let board = game_from_fen(fen).unwrap().board;
+            let divided = divide(&board, 2);
+            assert_eq!(divided.len() as u64, counts[0], "{name}");
+            assert_eq!(divided.values().sum::<u64>(), counts[1], "{name}");
+        }
+    }
+
+    #[test]
+    fn divide_keys_are_long_algebraic() {
+        let board = game_from_fen(STARTPOS).unwrap().board;
+        let divided = divide(&board, 1);
+        assert_eq!(divided.get("e2e4"), Some(&1));
+        assert_eq!(divided.get("g1f3"), Some(&1));
+        assert!(divide(&board, 0).is_empty());
+    }
+}
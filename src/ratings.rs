@@ -0,0 +1,316 @@
+use std::{collections::HashMap, path::PathBuf, process::ExitCode};
+
+use clap::{Parser, ValueEnum};
+use mintymacks::utils::{eprintln_async, println_async};
+
+use crate::{
+    Runnable,
+    elo::Tally,
+    results::{GameRecord, default_path, load},
+};
+
+/// How ratings are computed from the recorded games.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Method {
+    /// Incremental updates in the order the games were played
+    Elo,
+    /// Maximum likelihood with draws and the first move advantage modelled,
+    /// and a prior pulling players with few games to the average
+    Bayes,
+    /// Maximum likelihood counting draws as half a win
+    #[default]
+    Ordo,
+}
+
+#[derive(Parser)]
+pub struct Ratings {
+    /// Results file [default: results.tsv in the config directory]
+    #[clap(long)]
+    pub results: Option<PathBuf>,
+
+    /// How to compute the ratings
+    #[clap(long, value_enum, default_value_t)]
+    pub method: Method,
+
+    /// Rating of the average player
+    #[clap(long, default_value_t = 0.0)]
+    pub average: f64,
+
+    /// Also print the results of every pair of players
+    #[clap(long)]
+    pub head_to_head: bool,
+}
+
+/// Change in rating per point scored above or below expectation.
+pub const K: f64 = 16.0;
+/// First move advantage in the Bayes model, as BayesElo assumes it.
+pub const ADVANTAGE: f64 = 32.8;
+/// How draw-prone the Bayes model takes games to be, as BayesElo assumes.
+pub const DRAW_ELO: f64 = 97.3;
+/// Virtual draws against the average player in the Bayes model.
+pub const PRIOR_DRAWS: f64 = 2.0;
+
+/// Expected score at a rating advantage.
+pub fn expected(d: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-d / 400.0))
+}
+
+/// Log-likelihood of white's points given white's rating advantage,
+/// counting a draw as half a win and half a loss.
+pub fn logistic_likelihood(d: f64, points: f64) -> f64 {
+    let e = expected(d);
+    points * e.ln() + (1.0 - points) * (1.0 - e).ln()
+}
+
+/// Log-likelihood of white's points given white's rating advantage, in
+/// the model of BayesElo.
+pub fn bayes_likelihood(d: f64, points: f64) -> f64 {
+    let win = expected(d + ADVANTAGE - DRAW_ELO);
+    let loss = expected(-d - ADVANTAGE - DRAW_ELO);
+    if points > 0.75 {
+        win.ln()
+    } else if points > 0.25 {
+        (1.0 - win - loss).ln()
+    } else {
+        loss.ln()
+    }
+}
+
+/// Ratings in the order games were played, starting from the average.
+pub fn incremental(players: usize, games: &[(usize, usize, f64)]) -> Vec<f64> {
+    let mut ratings = vec![0.0; players];
+    for &(white, black, points) in games {
+        let change = K * (points - expected(ratings[white] - ratings[black]));
+        ratings[white] += change;
+        ratings[black] -= change;
+    }
+    ratings
+}
+
+/// Ratings maximizing the likelihood of the games, with the margin of a
+/// 95% confidence interval from the curvature of the likelihood. Players
+/// who won or lost every game drift to the bounds.
+pub fn maximum_likelihood(
+    players: usize,
+    games: &[(usize, usize, f64)],
+    likelihood: fn(f64, f64) -> f64,
+    prior_draws: f64,
+) -> Vec<(f64, f64)> {
+    const BOUND: f64 = 2000.0;
+    const H: f64 = 1.0;
+
+    let player_likelihood = |ratings: &[f64], player: usize, x: f64| {
+        let played = games
+            .iter()
+            .map(|&(white, black, points)| {
+                if white == player {
+                    likelihood(x - ratings[black], points)
+                } else if black == player {
+                    likelihood(ratings[white] - x, points)
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f64>();
+        // half the virtual draws with each color
+        played + prior_draws * (likelihood(x, 0.5) + likelihood(-x, 0.5)) / 2.0
+    };
+
+    // slope and curvature by finite differences
+    let derivatives = |ratings: &[f64], player: usize| {
+        let x = ratings[player];
+        let below = player_likelihood(ratings, player, x - H);
+        let at = player_likelihood(ratings, player, x);
+        let above = player_likelihood(ratings, player, x + H);
+        (
+            (above - below) / (2.0 * H),
+            (above - 2.0 * at + below) / (H * H),
+        )
+    };
+
+    let mut ratings = vec![0.0; players];
+    for _ in 0..1000 {
+        let mut largest = 0.0f64;
+        for player in 0..players {
+            let (slope, curvature) = derivatives(&ratings, player);
+            let step = if curvature < 0.0 {
+                -slope / curvature
+            } else {
+                slope.signum() * 100.0
+            }
+            .clamp(-100.0, 100.0);
+
+            ratings[player] = (ratings[player] + step).clamp(-BOUND, BOUND);
+            largest = largest.max(step.abs());
+        }
+        if largest < 0.01 {
+            break;
+        }
+    }
+
+    let mean = ratings.iter().sum::<f64>() / players.max(1) as f64;
+    (0..players)
+        .map(|player| {
+            let (_, curvature) = derivatives(&ratings, player);
+            let margin = if curvature < 0.0 {
+                1.96 / (-curvature).sqrt()
+            } else {
+                f64::INFINITY
+            };
+            (ratings[player] - mean, margin)
+        })
+        .collect()
+}
+
+impl Runnable for Ratings {
+    async fn run(self) -> tokio::io::Result<()> {
+        let Some(path) = self.results.clone().or_else(default_path) else {
+            eprintln_async!("No results file given and no config directory").await;
+            ExitCode::FAILURE.exit_process();
+        };
+
+        let records = match load(&path).await {
+            Ok(records) => records,
+            Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        if records.is_empty() {
+            eprintln_async!("No games recorded in {}", path.to_string_lossy()).await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        let mut names: Vec<String> = vec![];
+        let mut index = |name: &str| match names.iter().position(|n| n == name) {
+            Some(ix) => ix,
+            None => {
+                names.push(name.to_string());
+                names.len() - 1
+            }
+        };
+        let games = records
+            .iter()
+            .map(
+                |GameRecord {
+                     white,
+                     black,
+                     points,
+                     ..
+                 }| (index(white), index(black), *points),
+            )
+            .collect::<Vec<_>>();
+
+        let mut tallies = vec![Tally::default(); names.len()];
+        let mut pairs = HashMap::<(usize, usize), Tally>::new();
+        for &(white, black, points) in &games {
+            tallies[white].add(points);
+            tallies[black].add(1.0 - points);
+            pairs.entry((white, black)).or_default().add(points);
+            pairs.entry((black, white)).or_default().add(1.0 - points);
+        }
+
+        let rated: Vec<(f64, f64)> = match self.method {
+            Method::Elo => incremental(names.len(), &games)
+                .into_iter()
+                .zip(&tallies)
+                .map(|(rating, tally)| {
+                    (rating, tally.elo().map(|(_, m)| m).unwrap_or(f64::INFINITY))
+                })
+                .collect(),
+            Method::Bayes => maximum_likelihood(names.len(), &games, bayes_likelihood, PRIOR_DRAWS),
+            Method::Ordo => maximum_likelihood(names.len(), &games, logistic_likelihood, 0.0),
+        };
+
+        let mut order = (0..names.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| rated[*b].0.total_cmp(&rated[*a].0));
+
+        let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+        println_async!(
+            "{:>4}  {:<width$}  {:>6}  {:>5}  {:>6}  {:>6}",
+            "Rank",
+            "Name",
+            "Rating",
+            "±",
+            "Games",
+            "Score"
+        )
+        .await;
+        for (rank, &player) in order.iter().enumerate() {
+            let (rating, margin) = rated[player];
+            let tally = tallies[player];
+            println_async!(
+                "{:>4}  {:<width$}  {:>6.0}  {:>5.0}  {:>6}  {:>5.1}%",
+                rank + 1,
+                names[player],
+                rating + self.average,
+                margin,
+                tally.games(),
+                100.0 * tally.points() / tally.games() as f64
+            )
+            .await;
+        }
+
+        if self.head_to_head {
+            for &player in &order {
+                println_async!("\n{}", names[player]).await;
+                for &opponent in &order {
+                    let Some(tally) = pairs.get(&(player, opponent)) else {
+                        continue;
+                    };
+                    println_async!(
+                        "  vs {:<width$}  {tally}  {}/{}",
+                        names[opponent],
+                        tally.points(),
+                        tally.games()
+                    )
+                    .await;
+                }
+            }
+        }
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elo::elo_difference;
+
+    #[test]
+    fn three_wins_in_four() {
+        let games = [(0, 1, 1.0), (1, 0, 0.0), (0, 1, 1.0), (1, 0, 1.0)];
+        let ratings = maximum_likelihood(2, &games, logistic_likelihood, 0.0);
+
+        let gap = elo_difference(0.75);
+        assert!((ratings[0].0 - gap / 2.0).abs() < 1.0, "{ratings:?}");
+        assert!((ratings[1].0 + gap / 2.0).abs() < 1.0, "{ratings:?}");
+        assert!(ratings[0].1.is_finite() && ratings[0].1 > 0.0);
+    }
+
+    #[test]
+    fn winning_every_game_drifts_to_the_bounds() {
+        let games = [(0, 1, 1.0), (1, 0, 0.0), (0, 1, 1.0), (1, 0, 0.0)];
+        let ratings = maximum_likelihood(2, &games, logistic_likelihood, 0.0);
+
+        assert!(ratings[0].0 > 1000.0, "{ratings:?}");
+        assert!(ratings[1].0 < -1000.0, "{ratings:?}");
+        assert!(ratings[0].1 > 1000.0, "{ratings:?}");
+    }
+
+    #[test]
+    fn the_prior_keeps_perfect_scores_finite() {
+        let games = [(0, 1, 1.0), (1, 0, 0.0)];
+        let ratings = maximum_likelihood(2, &games, bayes_likelihood, PRIOR_DRAWS);
+
+        assert!(ratings[0].0 > 0.0 && ratings[0].0 < 1000.0, "{ratings:?}");
+        assert!(ratings[0].1.is_finite());
+    }
+
+    #[test]
+    fn incremental_updates_are_zero_sum() {
+        assert_eq!(incremental(2, &[(0, 1, 1.0)]), vec![K / 2.0, -K / 2.0]);
+
+        let games = [(0, 1, 1.0), (1, 2, 0.5), (2, 0, 0.0)];
+        assert!(incremental(3, &games).iter().sum::<f64>().abs() < 1e-9);
+    }
+}
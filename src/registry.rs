@@ -0,0 +1,504 @@
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::OnceLock,
+};
+
+use clap::{Parser, Subcommand};
+use mintymacks::{
+    profile::{EngineProfile, Profile},
+    utils::{eprintln_async, println_async},
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, stderr, stdin};
+
+use crate::{
+    Runnable,
+    new_profile::{profile_dir, profile_file_name},
+};
+
+static DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// Sets the directories searched for profiles. Only the first call has an
+/// effect, and without one the profile directory is searched.
+pub fn install_dirs(dirs: Vec<PathBuf>) {
+    let _ = DIRS.set(dirs);
+}
+
+pub fn dirs() -> Vec<PathBuf> {
+    DIRS.get_or_init(|| profile_dir().into_iter().collect())
+        .clone()
+}
+
+/// A profile found in one of the directories.
+pub struct RegistryEntry {
+    pub path: PathBuf,
+    pub profile: Profile,
+}
+
+impl RegistryEntry {
+    pub fn name(&self) -> &str {
+        match &self.profile {
+            Profile::Engine(p) => &p.engine.name,
+            Profile::Player(p) => &p.human.name,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match &self.profile {
+            Profile::Engine(_) => "bot",
+            Profile::Player(_) => "player",
+        }
+    }
+
+    /// The file name without `.toml`, which is how profiles are usually
+    /// referred to.
+    pub fn stem(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Whether `name` is the file name of this profile, ignoring case.
+    pub fn has_stem(&self, name: &str) -> bool {
+        let name = name.trim_end_matches(".toml");
+        self.stem().eq_ignore_ascii_case(name)
+            || self.stem() == profile_file_name(name).trim_end_matches(".toml")
+    }
+
+    /// Whether `name` is the name inside this profile, ignoring case.
+    pub fn has_name(&self, name: &str) -> bool {
+        self.name()
+            .eq_ignore_ascii_case(name.trim_end_matches(".toml"))
+    }
+}
+
+/// All profiles in the configured directories.
+pub struct Registry {
+    pub entries: Vec<RegistryEntry>,
+    /// Profile files that could not be read, with the reason.
+    pub errors: Vec<(PathBuf, String)>,
+}
+
+impl Registry {
+    pub async fn scan() -> tokio::io::Result<Self> {
+        let mut entries = vec![];
+        let mut errors = vec![];
+
+        for dir in dirs() {
+            let mut listing = match tokio::fs::read_dir(&dir).await {
+                Ok(listing) => listing,
+                Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            while let Some(entry) = listing.next_entry().await? {
+                let path = entry.path();
+                if path.extension().is_none_or(|e| e != "toml") {
+                    continue;
+                }
+
+                let table = match load_table(&path).await {
+                    Ok(table) => table,
+                    Err(e) => {
+                        errors.push((path, e.to_string()));
+                        continue;
+                    }
+                };
+                // bot profiles are written without the enum wrapper
+                let is_bot = table.contains_key("engine");
+                let profile = match toml::Value::Table(table.clone()).try_into::<EngineProfile>() {
+                    Ok(p) => Profile::Engine(p),
+                    Err(e) if is_bot => {
+                        errors.push((path, e.to_string()));
+                        continue;
+                    }
+                    Err(_) => match toml::Value::Table(table).try_into::<Profile>() {
+                        Ok(p) => p,
+                        Err(e) => {
+                            errors.push((path, e.to_string()));
+                            continue;
+                        }
+                    },
+                };
+
+                entries.push(RegistryEntry { path, profile });
+            }
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        errors.sort();
+        Ok(Registry { entries, errors })
+    }
+
+    /// The one profile a name refers to, by file name, or else by the name
+    /// inside it.
+    pub fn find(&self, name: &str) -> Result<&RegistryEntry, String> {
+        let mut found = self
+            .entries
+            .iter()
+            .filter(|e| e.has_stem(name))
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            found = self.entries.iter().filter(|e| e.has_name(name)).collect();
+        }
+
+        match &found[..] {
+            [] => Err(format!("No profile named `{name}'")),
+            [entry] => Ok(entry),
+            more => Err(format!(
+                "Profile name `{name}' is ambiguous: {}",
+                more.iter()
+                    .map(|e| e.path.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+/// Reads a profile as a table, with the profile it `extends` filled in
+/// underneath.
+pub async fn load_table(path: &Path) -> tokio::io::Result<toml::Table> {
+    load_extending(path, &mut vec![]).await
+}
+
+/// The table a profile inherits through `extends`, empty if it extends
+/// nothing.
+pub async fn load_inherited(path: &Path) -> tokio::io::Result<toml::Table> {
+    // checks the whole chain, so that the base is known to load
+    load_table(path).await?;
+
+    let data = tokio::fs::read(path).await?;
+    let own = toml::from_slice::<toml::Table>(&data)
+        .map_err(|e| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e))?;
+    let base = own
+        .get("extends")
+        .and_then(toml::Value::as_str)
+        .and_then(|base| find_base(path, base));
+
+    match base {
+        Some(base) => load_table(&base).await,
+        None => Ok(toml::Table::new()),
+    }
+}
+
+async fn load_extending(path: &Path, seen: &mut Vec<PathBuf>) -> tokio::io::Result<toml::Table> {
+    let invalid = |e: String| tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, e);
+
+    let canonical = tokio::fs::canonicalize(path).await?;
+    if seen.contains(&canonical) {
+        return Err(invalid(format!(
+            "Profile {} extends itself",
+            path.to_string_lossy()
+        )));
+    }
+    seen.push(canonical);
+
+    let data = tokio::fs::read(path).await?;
+    let mut table = toml::from_slice::<toml::Table>(&data).map_err(|e| invalid(e.to_string()))?;
+
+    let Some(base) = table.remove("extends") else {
+        return Ok(table);
+    };
+    let Some(base) = base.as_str() else {
+        return Err(invalid(String::from("`extends' takes a profile file name")));
+    };
+
+    let base = find_base(path, base).ok_or_else(|| {
+        invalid(format!(
+            "Profile {} extends {base}, which was not found",
+            path.to_string_lossy()
+        ))
+    })?;
+    let mut res = Box::pin(load_extending(&base, seen)).await?;
+    merge(&mut res, table);
+    Ok(res)
+}
+
+/// The file a profile extends, next to the profile or in one of the
+/// profile directories.
+fn find_base(path: &Path, base: &str) -> Option<PathBuf> {
+    let beside = path.parent().unwrap_or(Path::new(".")).join(base);
+    if beside.exists() {
+        return Some(beside);
+    }
+
+    dirs()
+        .into_iter()
+        .flat_map(|dir| {
+            [dir.join(base), dir.join(profile_file_name(base.trim_end_matches(".toml")))]
+        })
+        .find(|p| p.exists())
+}
+
+/// Overlays `top` on `base`, merging tables key by key.
+pub fn merge(base: &mut toml::Table, top: toml::Table) {
+    for (key, value) in top {
+        let value = match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(below)), toml::Value::Table(above)) => {
+                merge(below, above);
+                continue;
+            }
+            (_, value) => value,
+        };
+        base.insert(key, value);
+    }
+}
+
+/// An option override like `Skill Level=5`, with numbers and booleans
+/// typed as such.
+pub fn parse_overlay(s: &str) -> Result<(String, toml::Value), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected NAME=VALUE, got `{s}'"))?;
+
+    Ok((name.trim().to_string(), parse_value(value)))
+}
+
+/// An option value as typed on the command line.
+pub fn parse_value(s: &str) -> toml::Value {
+    match s.trim() {
+        "true" => toml::Value::Boolean(true),
+        "false" => toml::Value::Boolean(false),
+        v => match v.parse::<i64>() {
+            Ok(n) => toml::Value::Integer(n),
+            Err(_) => toml::Value::String(v.to_string()),
+        },
+    }
+}
+
+/// Whether a profile argument is a file rather than a registry name: one
+/// with a directory or a `.toml` extension. Bare names never refer to files,
+/// so a stray file in the working directory cannot shadow a profile.
+pub fn is_profile_file(path: &Path) -> bool {
+    path.components().count() > 1 || path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// A profile file as given, or else the profile it names in the registry.
+pub async fn resolve_profile(path: &Path) -> tokio::io::Result<PathBuf> {
+    if is_profile_file(path) {
+        return Ok(path.to_path_buf());
+    }
+
+    match Registry::scan().await?.find(&path.to_string_lossy()) {
+        Ok(entry) => Ok(entry.path.clone()),
+        Err(e) => Err(tokio::io::Error::new(tokio::io::ErrorKind::NotFound, e)),
+    }
+}
+
+#[derive(Parser)]
+pub struct ProfilesCommand {
+    #[clap(subcommand)]
+    command: ProfilesSubCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ProfilesSubCommand {
+    /// List all profiles
+    List,
+    /// Print a profile
+    Show {
+        /// Profile file or name
+        name: String,
+    },
+    /// Delete a profile
+    Rm {
+        /// Profile file or name
+        name: String,
+
+        /// Do not ask for confirmation
+        #[clap(long, short)]
+        yes: bool,
+    },
+}
+
+impl Runnable for ProfilesCommand {
+    async fn run(self) -> tokio::io::Result<()> {
+        let registry = Registry::scan().await?;
+
+        match self.command {
+            ProfilesSubCommand::List => {
+                for entry in &registry.entries {
+                    println_async!(
+                        "{:<20} {:<7} {:<24} {}",
+                        entry.stem(),
+                        entry.kind(),
+                        entry.name(),
+                        entry.path.to_string_lossy()
+                    )
+                    .await;
+                }
+                for (path, e) in &registry.errors {
+                    eprintln_async!("Could not read {}: {e}", path.to_string_lossy()).await;
+                }
+            }
+            ProfilesSubCommand::Show { name } => {
+                let path = found(&registry, &name).await;
+                let text = String::from_utf8_lossy_owned(tokio::fs::read(&path).await?);
+                println_async!("# {}\n{}", path.to_string_lossy(), text.trim_end()).await;
+            }
+            ProfilesSubCommand::Rm { name, yes } => {
+                let path = found(&registry, &name).await;
+
+                if !yes {
+                    if !std::io::stdin().is_terminal() {
+                        eprintln_async!("Not removing without --yes").await;
+                        ExitCode::FAILURE.exit_process();
+                    }
+
+                    stderr()
+                        .write_all(format!("Remove {}? [y/N] ", path.to_string_lossy()).as_bytes())
+                        .await?;
+                    stderr().flush().await?;
+                    let mut answer = String::new();
+                    BufReader::new(stdin()).read_line(&mut answer).await?;
+                    if !answer.trim().eq_ignore_ascii_case("y") {
+                        ExitCode::FAILURE.exit_process();
+                    }
+                }
+
+                tokio::fs::remove_file(&path).await?;
+                eprintln_async!("Removed {}", path.to_string_lossy()).await;
+            }
+        }
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+async fn found(registry: &Registry, name: &str) -> PathBuf {
+    let path = Path::new(name);
+    if is_profile_file(path) {
+        return path.to_path_buf();
+    }
+
+    match registry.find(name) {
+        Ok(entry) => entry.path.clone(),
+        Err(e) => {
+            eprintln_async!("{e}").await;
+            ExitCode::FAILURE.exit_process();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, name: &str) -> RegistryEntry {
+        let profile = toml::from_str::<EngineProfile>(&format!(
+            "[engine]\nname = \"{name}\"\nauthor = \"\"\ncommand = [\"{file}\", []]\nlog = false\n\n[options]\n"
+        ))
+        .unwrap();
+
+        RegistryEntry {
+            path: PathBuf::from(format!("/profiles/{file}.toml")),
+            profile: Profile::Engine(profile),
+        }
+    }
+
+    #[test]
+    fn file_names_come_before_inner_names() {
+        let registry = Registry {
+            entries: vec![entry("stockfish", "Stockfish 17"), entry("suckfish", "Stockfish")],
+            errors: vec![],
+        };
+
+        assert_eq!(registry.find("stockfish").unwrap().stem(), "stockfish");
+        assert_eq!(registry.find("Suckfish.toml").unwrap().stem(), "suckfish");
+        assert_eq!(registry.find("stockfish 17").unwrap().stem(), "stockfish");
+        assert!(registry.find("komodo").is_err());
+    }
+
+    #[test]
+    fn ambiguous_names() {
+        let registry = Registry {
+            entries: vec![entry("a", "Same"), entry("b", "same")],
+            errors: vec![],
+        };
+
+        assert!(registry.find("same").unwrap_err().contains("ambiguous"));
+        assert_eq!(registry.find("a").unwrap().stem(), "a");
+    }
+
+    #[test]
+    fn merge_overlays_tables() {
+        let mut base = toml::from_str::<toml::Table>(
+            "[engine]\nname = \"Base\"\nlog = false\n\n[options]\nHash = 16\nThreads = 4\n",
+        )
+        .unwrap();
+        let top = toml::from_str::<toml::Table>(
+            "[engine]\nname = \"Top\"\n\n[options]\nThreads = 1\n\"Skill Level\" = 0\n",
+        )
+        .unwrap();
+
+        merge(&mut base, top);
+
+        let expected = toml::from_str::<toml::Table>(
+            "[engine]\nname = \"Top\"\nlog = false\n\n[options]\nHash = 16\nThreads = 1\n\"Skill Level\" = 0\n",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn values_are_typed() {
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(parse_value(" false "), toml::Value::Boolean(false));
+        assert_eq!(parse_value("-5"), toml::Value::Integer(-5));
+        assert_eq!(parse_value("1.5"), toml::Value::String("1.5".into()));
+        assert_eq!(
+            parse_value(" nn.nnue "),
+            toml::Value::String("nn.nnue".into())
+        );
+
+        assert_eq!(
+            parse_overlay("Skill Level = 5"),
+            Ok((String::from("Skill Level"), toml::Value::Integer(5)))
+        );
+        assert!(parse_overlay("Skill Level").is_err());
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn extends_fills_in_the_base() {
+        let dir = scratch_dir("registry-extends");
+        std::fs::write(dir.join("base.toml"), "[options]\nHash = 16\nThreads = 4\n").unwrap();
+        std::fs::write(
+            dir.join("top.toml"),
+            "extends = \"base.toml\"\n\n[options]\nThreads = 1\n",
+        )
+        .unwrap();
+
+        let table = load_table(&dir.join("top.toml")).await.unwrap();
+        let inherited = load_inherited(&dir.join("top.toml")).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!table.contains_key("extends"));
+        assert_eq!(table["options"]["Hash"].as_integer(), Some(16));
+        assert_eq!(table["options"]["Threads"].as_integer(), Some(1));
+        assert_eq!(inherited["options"]["Threads"].as_integer(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn extends_cycles_are_errors() {
+        let dir = scratch_dir("registry-cycle");
+        std::fs::write(dir.join("a.toml"), "extends = \"b.toml\"\n").unwrap();
+        std::fs::write(dir.join("b.toml"), "extends = \"a.toml\"\n").unwrap();
+        std::fs::write(dir.join("c.toml"), "extends = \"c.toml\"\n").unwrap();
+
+        let a = load_table(&dir.join("a.toml")).await;
+        let c = load_table(&dir.join("c.toml")).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(a.unwrap_err().to_string().contains("extends itself"));
+        assert!(c.unwrap_err().to_string().contains("extends itself"));
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::Args;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{config_dir, registry::resolve_profile, sweep::label};
+
+/// `results.tsv` in the config directory.
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("results.tsv"))
+}
+
+/// How a profile is told apart in the results, by its file name and any
+/// options set over it on the command line. Profiles often share the name
+/// of the engine they run, so that name would mix their games up.
+pub async fn identity(path: &Path, overlay: &[(String, toml::Value)]) -> tokio::io::Result<String> {
+    let path = resolve_profile(path).await?;
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if overlay.is_empty() {
+        Ok(stem)
+    } else {
+        Ok(format!("{stem} ({})", label(overlay)))
+    }
+}
+
+/// One finished game, a line of the results file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    pub white: String,
+    pub black: String,
+    /// Points for white
+    pub points: f64,
+}
+
+impl GameRecord {
+    /// Parses a line like `1760000000	Stockfish 17	Suckfish 17	1-0`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let time = fields.next()?.parse().ok()?;
+        let white = fields.next()?.to_string();
+        let black = fields.next()?.to_string();
+        let points = match fields.next()?.trim() {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            _ => return None,
+        };
+
+        Some(GameRecord {
+            time,
+            white,
+            black,
+            points,
+        })
+    }
+}
+
+impl Display for GameRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let result = if self.points > 0.75 {
+            "1-0"
+        } else if self.points > 0.25 {
+            "1/2-1/2"
+        } else {
+            "0-1"
+        };
+        write!(
+            f,
+            "{}\t{}\t{}\t{result}",
+            self.time,
+            self.white.replace('\t', " "),
+            self.black.replace('\t', " ")
+        )
+    }
+}
+
+/// Where matches record their games for `ratings`.
+#[derive(Args, Clone, Debug, Default)]
+pub struct RecordOptions {
+    /// Results file to add the games to [default: results.tsv in the config directory]
+    #[clap(long)]
+    pub results: Option<PathBuf>,
+
+    /// Do not add the games to the results file
+    #[clap(long, conflicts_with = "results")]
+    pub no_record: bool,
+}
+
+impl RecordOptions {
+    pub fn path(&self) -> Option<PathBuf> {
+        if self.no_record {
+            return None;
+        }
+        self.results.clone().or_else(default_path)
+    }
+}
+
+/// Appends a game to the results file, creating it if needed.
+pub async fn record(path: &Path, white: &str, black: &str, points: f64) -> tokio::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+
+    let record = GameRecord {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        white: white.to_string(),
+        black: black.to_string(),
+        points,
+    };
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(format!("{record}\n").as_bytes()).await?;
+    file.flush().await
+}
+
+/// All games in a results file, skipping lines that do not parse.
+pub async fn load(path: &Path) -> tokio::io::Result<Vec<GameRecord>> {
+    let text = String::from_utf8_lossy_owned(tokio::fs::read(path).await?);
+    Ok(text.lines().filter_map(GameRecord::parse).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        for (line, points) in [
+            ("1760000000\tstockfish\tsuckfish (Skill Level=5)\t1-0", 1.0),
+            ("1760000001\tsuckfish\tstockfish\t0-1", 0.0),
+            ("1760000002\tstockfish\tsuckfish\t1/2-1/2", 0.5),
+        ] {
+            let record = GameRecord::parse(line).unwrap();
+            assert_eq!(record.points, points);
+            assert_eq!(record.to_string(), line);
+        }
+    }
+
+    #[test]
+    fn tabs_in_names_are_replaced() {
+        let record = GameRecord {
+            time: 1,
+            white: String::from("a\tb"),
+            black: String::from("c"),
+            points: 0.5,
+        };
+        assert_eq!(record.to_string(), "1\ta b\tc\t1/2-1/2");
+    }
+
+    #[test]
+    fn bad_lines() {
+        assert_eq!(GameRecord::parse(""), None);
+        assert_eq!(GameRecord::parse("soon\ta\tb\t1-0"), None);
+        assert_eq!(GameRecord::parse("1\ta\tb"), None);
+        assert_eq!(GameRecord::parse("1\ta\tb\t*"), None);
+    }
+}
@@ -0,0 +1,210 @@
+use std::{fmt::Display, time::Duration};
+
+use mintymacks::notation::{
+    LongAlg,
+    uci::engine::{InfoField, UciEngine},
+};
+
+/// Engine evaluation, from the point of view of the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Cp(i32),
+    /// Mate in so many moves, negative if the side to move gets mated
+    Mate(i32),
+}
+
+impl Score {
+    pub const MATE: i32 = 100_000;
+
+    /// Centipawns, with mates as very large values.
+    pub fn cp(self) -> i32 {
+        match self {
+            Score::Cp(cp) => cp,
+            Score::Mate(n) if n > 0 => Self::MATE - n,
+            Score::Mate(n) => -Self::MATE - n,
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        match self {
+            Score::Cp(cp) => Score::Cp(-cp),
+            Score::Mate(n) => Score::Mate(-n),
+        }
+    }
+}
+
+impl Display for Score {
+    /// Pawns with sign, or `+M5`/`-M3` for mates, as cutechess writes them.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Score::Cp(cp) => write!(f, "{:+.2}", cp as f64 / 100.0),
+            Score::Mate(n) if n >= 0 => write!(f, "+M{n}"),
+            Score::Mate(n) => write!(f, "-M{}", -n),
+        }
+    }
+}
+
+/// The fields of a UCI `info` line that the matches and analysis care about.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<Score>,
+    /// Win, draw and loss chances in permille, sent with `UCI_ShowWDL`
+    pub wdl: Option<(u32, u32, u32)>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<Duration>,
+    /// Principal variation in long algebraic notation
+    pub pv: Vec<String>,
+}
+
+impl SearchInfo {
+    /// Reads an `info` message, returning `None` for other messages and
+    /// for info messages that carry neither a score nor a depth (`info
+    /// string` etc.).
+    pub fn from_uci(msg: &UciEngine) -> Option<Self> {
+        let UciEngine::Info(fields) = msg else {
+            return None;
+        };
+
+        let mut res = SearchInfo::default();
+
+        for field in fields {
+            match field {
+                InfoField::Depth(n) => res.depth = Some(*n),
+                InfoField::SelDepth(n) => res.seldepth = Some(*n),
+                InfoField::MultiPv(n) => res.multipv = Some(*n),
+                InfoField::Nodes(n) => res.nodes = Some(*n),
+                InfoField::Nps(n) => res.nps = Some(*n),
+                InfoField::Time(ms) => res.time = Some(Duration::from_millis(*ms)),
+                InfoField::ScoreCp(n) => res.score = Some(Score::Cp(*n)),
+                InfoField::ScoreMate(n) => res.score = Some(Score::Mate(*n)),
+                InfoField::Wdl(w, d, l) => res.wdl = Some((*w, *d, *l)),
+                InfoField::Pv(moves) => {
+                    res.pv = moves.iter().map(|(mv, pc)| mv.longalg(*pc)).collect();
+                }
+                InfoField::String(_) => return None,
+                _ => {}
+            }
+        }
+
+        if res.score.is_none() && res.depth.is_none() {
+            return None;
+        }
+
+        Some(res)
+    }
+
+    /// Folds a later `info` line of the same search into this one, keeping
+    /// fields the later line leaves out.
+    pub fn update(&mut self, later: SearchInfo) {
+        let SearchInfo {
+            depth,
+            seldepth,
+            multipv,
+            score,
+            wdl,
+            nodes,
+            nps,
+            time,
+            pv,
+        } = later;
+
+        self.depth = depth.or(self.depth);
+        self.seldepth = seldepth.or(self.seldepth);
+        self.multipv = multipv.or(self.multipv);
+        self.score = score.or(self.score);
+        self.wdl = wdl.or(self.wdl);
+        self.nodes = nodes.or(self.nodes);
+        self.nps = nps.or(self.nps);
+        self.time = time.or(self.time);
+        if !pv.is_empty() {
+            self.pv = pv;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(fields: Vec<InfoField>) -> Option<SearchInfo> {
+        SearchInfo::from_uci(&UciEngine::Info(fields))
+    }
+
+    #[test]
+    fn reads_fields() {
+        let info = info(vec![
+            InfoField::Depth(21),
+            InfoField::SelDepth(30),
+            InfoField::MultiPv(2),
+            InfoField::ScoreCp(-34),
+            InfoField::Wdl(100, 800, 100),
+            InfoField::Nodes(123_456),
+            InfoField::Nps(1_000_000),
+            InfoField::Time(1_500),
+        ])
+        .unwrap();
+
+        assert_eq!(info.depth, Some(21));
+        assert_eq!(info.seldepth, Some(30));
+        assert_eq!(info.multipv, Some(2));
+        assert_eq!(info.score, Some(Score::Cp(-34)));
+        assert_eq!(info.wdl, Some((100, 800, 100)));
+        assert_eq!(info.nodes, Some(123_456));
+        assert_eq!(info.nps, Some(1_000_000));
+        assert_eq!(info.time, Some(Duration::from_millis(1_500)));
+        assert!(info.pv.is_empty());
+    }
+
+    #[test]
+    fn skips_other_messages() {
+        assert_eq!(SearchInfo::from_uci(&UciEngine::ReadyOk()), None);
+        assert_eq!(info(vec![InfoField::Nodes(10)]), None);
+        assert_eq!(
+            info(vec![
+                InfoField::Depth(3),
+                InfoField::String(String::from("hi"))
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn mate_scores() {
+        let info = info(vec![InfoField::ScoreMate(-3)]).unwrap();
+        assert_eq!(info.score, Some(Score::Mate(-3)));
+        assert_eq!(info.depth, None);
+    }
+
+    #[test]
+    fn update_keeps_missing_fields() {
+        let mut info = SearchInfo {
+            depth: Some(10),
+            score: Some(Score::Cp(20)),
+            nodes: Some(1_000),
+            pv: vec![String::from("e2e4"), String::from("e7e5")],
+            ..Default::default()
+        };
+        info.update(SearchInfo {
+            depth: Some(11),
+            nodes: Some(2_000),
+            ..Default::default()
+        });
+
+        assert_eq!(info.depth, Some(11));
+        assert_eq!(info.score, Some(Score::Cp(20)));
+        assert_eq!(info.nodes, Some(2_000));
+        assert_eq!(info.pv, ["e2e4", "e7e5"]);
+
+        info.update(SearchInfo {
+            score: Some(Score::Mate(4)),
+            pv: vec![String::from("d2d4")],
+            ..Default::default()
+        });
+        assert_eq!(info.score, Some(Score::Mate(4)));
+        assert_eq!(info.pv, ["d2d4"]);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::Parser;
+use mintymacks::utils::{eprintln_async, println_async};
+
+use crate::{
+    Runnable,
+    adjudicate::{AdjudicationRules, Adjudicator},
+    elo::Tally,
+    faceoff::{Contestant, MatchGame, read_profile, with_overlay},
+    registry::parse_value,
+    results::{RecordOptions, identity, record},
+};
+
+#[derive(Parser)]
+pub struct Sweep {
+    /// Bot profile whose options are varied, by file or by name
+    pub profile: PathBuf,
+
+    /// Bot profile every setting plays against, by file or by name
+    #[clap(long)]
+    pub reference: PathBuf,
+
+    /// Values to try for an option, like `"Skill Level=0..20"`, `"Skill Level=0..20:5"` in steps, `"Hash=16..1024*2"` in powers of two or `"Threads=1,2,4"`; given once or twice
+    #[clap(long = "option", value_parser = SweepRange::parse, required = true)]
+    pub options: Vec<SweepRange>,
+
+    /// Turn time in miliseconds
+    #[clap(long)]
+    pub time: u64,
+
+    /// Turn timeout in miliseconds
+    #[clap(long)]
+    pub timeout: u64,
+
+    /// Games per setting, alternating colors after each
+    #[clap(long, default_value_t = 10)]
+    pub games: usize,
+
+    #[clap(flatten)]
+    pub adjudication: AdjudicationRules,
+
+    #[clap(flatten)]
+    pub record: RecordOptions,
+}
+
+/// The values one option takes in a sweep.
+#[derive(Debug, Clone)]
+pub struct SweepRange {
+    pub name: String,
+    pub values: Vec<toml::Value>,
+}
+
+impl SweepRange {
+    /// Parses `Name=a..b`, `Name=a..b:step`, `Name=a..b*factor` or
+    /// `Name=x,y,z`, with ranges including both ends.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, values) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=VALUES, got `{s}'"))?;
+        let name = name.trim().to_string();
+        let values = values.trim();
+
+        let Some((from, rest)) = values.split_once("..") else {
+            return Ok(SweepRange {
+                name,
+                values: values.split(',').map(parse_value).collect(),
+            });
+        };
+
+        let int = |s: &str| {
+            s.trim()
+                .parse::<i64>()
+                .map_err(|_| format!("Expected a number in `{values}', got `{s}'"))
+        };
+        let from = int(from)?;
+
+        let mut res = vec![];
+        if let Some((to, factor)) = rest.split_once('*') {
+            let (to, factor) = (int(to)?, int(factor)?);
+            if from < 1 || factor < 2 {
+                return Err(format!("`{values}' does not grow"));
+            }
+            let mut n = Some(from);
+            while let Some(x) = n.filter(|x| *x <= to) {
+                res.push(x);
+                n = x.checked_mul(factor);
+            }
+        } else {
+            let (to, step) = match rest.split_once(':') {
+                Some((to, step)) => (int(to)?, int(step)?),
+                None => (int(rest)?, 1),
+            };
+            if step < 1 {
+                return Err(format!("`{values}' does not grow"));
+            }
+            res.extend((from..=to).step_by(step as usize));
+        }
+
+        if res.is_empty() {
+            return Err(format!("`{values}' is empty"));
+        }
+
+        Ok(SweepRange {
+            name,
+            values: res.into_iter().map(toml::Value::Integer).collect(),
+        })
+    }
+}
+
+/// Every combination of the values of the options.
+pub fn settings(options: &[SweepRange]) -> Vec<Vec<(String, toml::Value)>> {
+    options.iter().fold(vec![vec![]], |settings, option| {
+        settings
+            .iter()
+            .flat_map(|setting| {
+                option.values.iter().map(move |value| {
+                    let mut setting = setting.clone();
+                    setting.push((option.name.clone(), value.clone()));
+                    setting
+                })
+            })
+            .collect()
+    })
+}
+
+/// `Skill Level=5, Hash=64`, without quotes around strings.
+pub fn label(setting: &[(String, toml::Value)]) -> String {
+    setting
+        .iter()
+        .map(|(name, value)| match value {
+            toml::Value::String(s) => format!("{name}={s}"),
+            value => format!("{name}={value}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Runnable for Sweep {
+    async fn run(self) -> tokio::io::Result<()> {
+        if self.options.len() > 2 {
+            eprintln_async!("At most two options can be swept at once").await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        eprintln_async!("Loading profiles...").await;
+        let base = read_profile(&self.profile).await?;
+        let mut reference = Contestant::load(&self.reference).await?;
+        let reference_id = identity(&self.reference, &[]).await?;
+        let results = self.record.path();
+
+        let time = Duration::from_millis(self.time);
+        let timeout = Duration::from_millis(self.timeout);
+        let adjudicator = Adjudicator::new(self.adjudication.clone())?;
+
+        let settings = settings(&self.options);
+        let width = settings
+            .iter()
+            .map(|s| label(s).len())
+            .max()
+            .unwrap_or(0)
+            .max(7);
+
+        println_async!(
+            "{:<width$}  {:>14}  {:>7}  {:>12}",
+            "Setting",
+            "Results",
+            "Score",
+            "Elo"
+        )
+        .await;
+
+        let mut best: Option<(String, f64)> = None;
+        for (ix, setting) in settings.iter().enumerate() {
+            let label = label(setting);
+            eprintln_async!("Setting {} of {}: {label}", ix + 1, settings.len()).await;
+
+            let candidate_id = identity(&self.profile, setting).await?;
+            let mut candidate =
+                Contestant::from_profile(with_overlay(base.clone(), setting)).await?;
+            let mut tally = Tally::default();
+
+            for round in 1..=self.games {
+                let (white, black, white_id, black_id) = if round % 2 == 1 {
+                    (&mut candidate, &mut reference, &candidate_id, &reference_id)
+                } else {
+                    (&mut reference, &mut candidate, &reference_id, &candidate_id)
+                };
+                let game = MatchGame::play(white, black, &adjudicator, time, timeout).await?;
+
+                let (w, b) = game.points();
+                tally.add(if round % 2 == 1 { w } else { b });
+                if let Some(path) = &results {
+                    record(path, white_id, black_id, w).await?;
+                }
+                eprintln_async!("Game {round} of {}: {tally}", self.games).await;
+            }
+
+            candidate.engine.quit().await?;
+
+            let elo = match tally.elo() {
+                Some((elo, margin)) => {
+                    if best.as_ref().is_none_or(|(_, b)| elo > *b) {
+                        best = Some((label.clone(), elo));
+                    }
+                    format!("{elo:+.0} ± {margin:.0}")
+                }
+                None => String::from("-"),
+            };
+            println_async!(
+                "{label:<width$}  {:>14}  {:>7}  {elo:>12}",
+                tally.to_string(),
+                format!("{}/{}", tally.points(), tally.games()),
+            )
+            .await;
+        }
+
+        reference.engine.quit().await?;
+
+        if let Some((label, elo)) = best {
+            println_async!(
+                "Strongest against {}: {label} ({elo:+.0})",
+                reference.profile.engine.name
+            )
+            .await;
+        }
+
+        ExitCode::SUCCESS.exit_process();
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use mintymacks::notation::uci::{engine::UciEngine, gui::UciGui};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    time::Instant,
+};
+
+/// Which way a logged line went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+    /// Written by us, like the move markers
+    Note,
+}
+
+impl Direction {
+    pub fn symbol(self) -> char {
+        match self {
+            Direction::Sent => '>',
+            Direction::Received => '<',
+            Direction::Note => '#',
+        }
+    }
+}
+
+/// A line of a log, written like `    12.345 > go infinite`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub elapsed: Duration,
+    pub direction: Direction,
+    pub line: String,
+}
+
+impl LogEntry {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.trim_start().splitn(3, ' ');
+        let elapsed = Duration::from_secs_f64(parts.next()?.parse().ok()?);
+        let direction = match parts.next()? {
+            ">" => Direction::Sent,
+            "<" => Direction::Received,
+            "#" => Direction::Note,
+            _ => return None,
+        };
+        let line = parts.next().unwrap_or_default().to_string();
+
+        Some(LogEntry {
+            elapsed,
+            direction,
+            line,
+        })
+    }
+
+    /// The move a marker note starts, counting plies from 1.
+    pub fn move_marker(&self) -> Option<usize> {
+        match self.direction {
+            Direction::Note => self.line.strip_prefix("move ")?.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>10.3} {} {}",
+            self.elapsed.as_secs_f64(),
+            self.direction.symbol(),
+            self.line
+        )
+    }
+}
+
+/// The UCI traffic of one engine in one game, written as it happens when
+/// the profile sets `log`.
+pub struct UciLog {
+    pub file: Option<File>,
+    /// Entries waiting for the first file to be opened
+    pub pending: Option<Vec<LogEntry>>,
+    pub start: Instant,
+}
+
+impl UciLog {
+    pub fn disabled() -> Self {
+        UciLog {
+            file: None,
+            pending: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Entries a buffered log keeps before it drops the rest, plenty for a
+    /// start-up with all its options.
+    pub const PENDING_LIMIT: usize = 1000;
+
+    /// A log that keeps its first entries until `open` gives it a file, so
+    /// the engine start-up is logged before the first game.
+    pub fn buffered() -> Self {
+        UciLog {
+            file: None,
+            pending: Some(vec![]),
+            start: Instant::now(),
+        }
+    }
+
+    /// Continues the log in a new file, starting with any pending entries,
+    /// and returns its path. Times keep counting from the start of the log.
+    /// An existing file is left alone, the log goes to `<name>-2.log` and so
+    /// on instead.
+    pub async fn open(&mut self, path: &Path) -> tokio::io::Result<PathBuf> {
+        let (mut file, path) = Self::create_new(path).await?;
+        if let Some(old) = &mut self.file {
+            old.flush().await?;
+        }
+        for entry in self.pending.take().unwrap_or_default() {
+            file.write_all(format!("{entry}\n").as_bytes()).await?;
+        }
+        self.file = Some(file);
+        Ok(path)
+    }
+
+    async fn create_new(path: &Path) -> tokio::io::Result<(File, PathBuf)> {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+
+        let mut candidate = path.to_path_buf();
+        let mut n = 1;
+        loop {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&candidate)
+                .await
+            {
+                Ok(file) => return Ok((file, candidate)),
+                Err(e) if e.kind() == tokio::io::ErrorKind::AlreadyExists => {
+                    n += 1;
+                    candidate = path.with_file_name(format!("{stem}-{n}.{extension}"));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits for everything written so far to reach the file.
+    pub async fn flush(&mut self) -> tokio::io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// `<dir>/<engine>-game<round>-<color>.log`, with the engine name made
+    /// safe for file names.
+    pub fn path(dir: &Path, name: &str, round: usize, color: &str) -> PathBuf {
+        dir.join(format!("{}-game{round}-{color}.log", Self::file_name(name)))
+    }
+
+    /// The engine name in lower case, with anything but letters and digits
+    /// replaced by dashes.
+    pub fn file_name(name: &str) -> String {
+        name.chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+
+    pub async fn write(&mut self, direction: Direction, line: &str) -> tokio::io::Result<()> {
+        let entry = LogEntry {
+            elapsed: self.start.elapsed(),
+            direction,
+            line: line.trim_end().to_string(),
+        };
+
+        match (&mut self.file, &mut self.pending) {
+            (Some(file), _) => file.write_all(format!("{entry}\n").as_bytes()).await,
+            (None, Some(pending)) => {
+                if pending.len() < Self::PENDING_LIMIT {
+                    pending.push(entry);
+                }
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    pub async fn sent(&mut self, cmd: &UciGui) -> tokio::io::Result<()> {
+        self.write(Direction::Sent, &cmd.to_string()).await
+    }
+
+    pub async fn received(&mut self, msg: &UciEngine) -> tokio::io::Result<()> {
+        self.write(Direction::Received, &msg.to_string()).await
+    }
+
+    pub async fn note(&mut self, note: &str) -> tokio::io::Result<()> {
+        self.write(Direction::Note, note).await
+    }
+
+    /// Marks the start of the search for the given ply.
+    pub async fn move_marker(&mut self, ply: usize) -> tokio::io::Result<()> {
+        self.note(&format!("move {ply}")).await
+    }
+}
+
+/// A log read back for review, split at the move markers. Lines before the
+/// first marker are filed under ply 0.
+pub struct LogFile {
+    pub path: PathBuf,
+    pub sections: BTreeMap<usize, Vec<LogEntry>>,
+}
+
+impl LogFile {
+    pub async fn load(path: &Path) -> tokio::io::Result<Self> {
+        let text = String::from_utf8_lossy_owned(tokio::fs::read(path).await?);
+
+        let mut sections = BTreeMap::<usize, Vec<LogEntry>>::new();
+        let mut ply = 0;
+        for entry in text.lines().filter_map(LogEntry::parse) {
+            if let Some(marker) = entry.move_marker() {
+                ply = marker;
+            }
+            sections.entry(ply).or_default().push(entry);
+        }
+
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            sections,
+        })
+    }
+
+    pub fn section(&self, ply: usize) -> &[LogEntry] {
+        self.sections.get(&ply).map(|s| &s[..]).unwrap_or_default()
+    }
+}
@@ -0,0 +1,199 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use mintymacks::utils::{eprintln_async, println_async};
+
+use crate::{
+    Runnable,
+    check_engine::{AnnouncedOption, Outcome, Probe},
+    faceoff::read_profile,
+};
+
+#[derive(Parser)]
+pub struct ValidateProfile {
+    /// Bot profile
+    pub profile: PathBuf,
+}
+
+impl Runnable for ValidateProfile {
+    async fn run(self) -> tokio::io::Result<()> {
+        let profile = read_profile(&self.profile).await?;
+
+        let mut probe = match Probe::spawn(&profile.engine.command).await {
+            Ok(probe) => probe,
+            Err(e) => {
+                eprintln_async!("Unable to start engine: {e}").await;
+                ExitCode::FAILURE.exit_process();
+            }
+        };
+        let handshake = probe.handshake().await?;
+        probe.quit().await?;
+        if handshake.outcome != Outcome::Pass {
+            eprintln_async!("{handshake}").await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        let mut problems = 0;
+        for (name, value) in &profile.options {
+            let Some(option) = probe
+                .options
+                .iter()
+                .find(|o| o.name.eq_ignore_ascii_case(name))
+            else {
+                let hint = match closest(name, &probe.options) {
+                    Some(other) => format!(", did you mean `{other}'?"),
+                    None => String::new(),
+                };
+                println_async!("{name}: unknown option{hint}").await;
+                problems += 1;
+                continue;
+            };
+
+            if let Err(e) = check_value(option, value) {
+                println_async!("{name}: {e}").await;
+                problems += 1;
+            }
+        }
+
+        if problems > 0 {
+            println_async!("{problems} problems in {}", self.profile.to_string_lossy()).await;
+            ExitCode::FAILURE.exit_process();
+        }
+
+        println_async!(
+            "{}: {} options, all known to {}",
+            self.profile.to_string_lossy(),
+            profile.options.len(),
+            profile.engine.name
+        )
+        .await;
+        ExitCode::SUCCESS.exit_process();
+    }
+}
+
+/// Checks a profile value against the option the engine announced.
+pub fn check_value(option: &AnnouncedOption, value: &toml::Value) -> Result<(), String> {
+    match (&option.kind[..], value) {
+        ("spin", toml::Value::Integer(n)) => {
+            let min = option.min.as_deref().and_then(|m| m.parse::<i64>().ok());
+            let max = option.max.as_deref().and_then(|m| m.parse::<i64>().ok());
+            if min.is_some_and(|m| *n < m) || max.is_some_and(|m| *n > m) {
+                return Err(format!(
+                    "{n} is outside {}..{}",
+                    option.min.as_deref().unwrap_or("?"),
+                    option.max.as_deref().unwrap_or("?"),
+                ));
+            }
+            Ok(())
+        }
+        ("combo", toml::Value::String(s)) => {
+            // engines match option names and values without regard to case
+            if option.vars.iter().any(|v| v.eq_ignore_ascii_case(s)) {
+                Ok(())
+            } else {
+                Err(format!("`{s}' is not one of {}", option.vars.join(", ")))
+            }
+        }
+        ("check", toml::Value::Boolean(_)) => Ok(()),
+        ("button", _) => Err(String::from("buttons are pressed, not set in a profile")),
+        ("string", toml::Value::String(_)) => Ok(()),
+        (kind, value) => Err(format!("{} given for a {kind} option", value.type_str())),
+    }
+}
+
+/// The announced option nearest in spelling, for names that are probably
+/// typos.
+pub fn closest<'a>(name: &str, options: &'a [AnnouncedOption]) -> Option<&'a str> {
+    options
+        .iter()
+        .map(|o| {
+            (
+                edit_distance(&name.to_lowercase(), &o.name.to_lowercase()),
+                &o.name[..],
+            )
+        })
+        .filter(|(d, _)| *d <= 3)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, n)| n)
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(line: &str) -> AnnouncedOption {
+        AnnouncedOption::parse(line).unwrap()
+    }
+
+    #[test]
+    fn spin_bounds() {
+        let hash = option("option name Hash type spin default 16 min 1 max 1024");
+        assert!(check_value(&hash, &toml::Value::Integer(64)).is_ok());
+        assert!(check_value(&hash, &toml::Value::Integer(1)).is_ok());
+        assert!(check_value(&hash, &toml::Value::Integer(0)).is_err());
+        assert!(check_value(&hash, &toml::Value::Integer(2048)).is_err());
+        assert!(check_value(&hash, &toml::Value::String(String::from("64"))).is_err());
+    }
+
+    #[test]
+    fn combo_values_ignore_case() {
+        let style = option("option name Style type combo default Normal var Solid var Normal");
+        assert!(check_value(&style, &toml::Value::String(String::from("solid"))).is_ok());
+        assert!(check_value(&style, &toml::Value::String(String::from("Risky"))).is_err());
+    }
+
+    #[test]
+    fn checks_and_buttons() {
+        let ponder = option("option name Ponder type check default false");
+        assert!(check_value(&ponder, &toml::Value::Boolean(true)).is_ok());
+        assert!(check_value(&ponder, &toml::Value::Integer(1)).is_err());
+
+        let clear = option("option name Clear Hash type button");
+        assert!(check_value(&clear, &toml::Value::Boolean(true)).is_err());
+    }
+
+    #[test]
+    fn strings() {
+        let path = option("option name SyzygyPath type string default <empty>");
+        assert!(check_value(&path, &toml::Value::String(String::from("/tb"))).is_ok());
+        assert!(check_value(&path, &toml::Value::Boolean(false)).is_err());
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("hash", ""), 4);
+        assert_eq!(edit_distance("", "hash"), 4);
+        assert_eq!(edit_distance("hash", "hash"), 0);
+        assert_eq!(edit_distance("hahs", "hash"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest_names() {
+        let options = [
+            option("option name Hash type spin default 16 min 1 max 1024"),
+            option("option name Threads type spin default 1 min 1 max 512"),
+        ];
+        assert_eq!(closest("hsah", &options), Some("Hash"));
+        assert_eq!(closest("Thread", &options), Some("Threads"));
+        assert_eq!(closest("Contempt", &options), None);
+    }
+}
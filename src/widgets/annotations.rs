@@ -0,0 +1,592 @@
+use std::{collections::HashMap, ops::Range};
+
+use crossterm::{
+    event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    style,
+};
+use mintymacks::model::{Square, moves::ChessMove};
+
+use crate::widgets::board::BoardRenderer;
+
+/// Annotation colors, as used by the PGN `[%cal]` and `[%csl]` commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Tint {
+    Red,
+    #[default]
+    Green,
+    Blue,
+    Yellow,
+}
+
+impl Tint {
+    pub fn letter(self) -> char {
+        match self {
+            Tint::Red => 'R',
+            Tint::Green => 'G',
+            Tint::Blue => 'B',
+            Tint::Yellow => 'Y',
+        }
+    }
+
+    pub fn from_letter(c: char) -> Option<Self> {
+        match c {
+            'R' => Some(Tint::Red),
+            'G' => Some(Tint::Green),
+            'B' => Some(Tint::Blue),
+            'Y' => Some(Tint::Yellow),
+            _ => None,
+        }
+    }
+
+    /// Lichess-style modifier keys: plain green, [Shift] red, [Alt] blue,
+    /// [Shift]+[Alt] yellow.
+    pub fn from_modifiers(m: KeyModifiers) -> Self {
+        match (
+            m.contains(KeyModifiers::SHIFT),
+            m.contains(KeyModifiers::ALT),
+        ) {
+            (false, false) => Tint::Green,
+            (true, false) => Tint::Red,
+            (false, true) => Tint::Blue,
+            (true, true) => Tint::Yellow,
+        }
+    }
+
+    pub fn color(self) -> style::Color {
+        match self {
+            Tint::Red => style::Color::Rgb {
+                r: 0xDD,
+                g: 0x33,
+                b: 0x33,
+            },
+            Tint::Green => style::Color::Rgb {
+                r: 0x33,
+                g: 0xBB,
+                b: 0x33,
+            },
+            Tint::Blue => style::Color::Rgb {
+                r: 0x33,
+                g: 0x66,
+                b: 0xDD,
+            },
+            Tint::Yellow => style::Color::Rgb {
+                r: 0xDD,
+                g: 0xCC,
+                b: 0x22,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arrow {
+    pub from: Square,
+    pub to: Square,
+    pub tint: Tint,
+}
+
+impl Arrow {
+    pub fn from_move(mv: ChessMove, tint: Tint) -> Self {
+        Arrow {
+            from: mv.pmv.from,
+            to: mv.pmv.to,
+            tint,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mark {
+    pub sq: Square,
+    pub tint: Tint,
+}
+
+/// Arrows and colored squares drawn over a board.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Annotations {
+    pub arrows: Vec<Arrow>,
+    pub marks: Vec<Mark>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.marks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.arrows.clear();
+        self.marks.clear();
+    }
+
+    /// Adds the arrow, or removes it if it already exists. An arrow between
+    /// the same squares in another color is replaced.
+    pub fn toggle_arrow(&mut self, arrow: Arrow) {
+        let existed = self.arrows.contains(&arrow);
+        self.arrows
+            .retain(|a| (a.from, a.to) != (arrow.from, arrow.to));
+        if !existed {
+            self.arrows.push(arrow);
+        }
+    }
+
+    pub fn toggle_mark(&mut self, mark: Mark) {
+        let existed = self.marks.contains(&mark);
+        self.marks.retain(|m| m.sq != mark.sq);
+        if !existed {
+            self.marks.push(mark);
+        }
+    }
+
+    pub fn mark(&self, sq: Square) -> Option<Tint> {
+        self.marks.iter().find(|m| m.sq == sq).map(|m| m.tint)
+    }
+
+    /// Reads the `[%cal ...]` and `[%csl ...]` commands of a PGN comment.
+    pub fn parse(comment: &str) -> Self {
+        let mut res = Self::default();
+
+        for (cmd, args) in commands(comment) {
+            for arg in args.split(',').map(str::trim) {
+                let mut chars = arg.chars();
+                let Some(tint) = chars.next().and_then(Tint::from_letter) else {
+                    continue;
+                };
+                let rest = chars.as_str();
+
+                match cmd {
+                    "cal" if rest.len() == 4 => {
+                        if let (Some(from), Some(to)) =
+                            (parse_square(&rest[..2]), parse_square(&rest[2..]))
+                        {
+                            res.arrows.push(Arrow { from, to, tint });
+                        }
+                    }
+                    "csl" => {
+                        if let Some(sq) = parse_square(rest) {
+                            res.marks.push(Mark { sq, tint });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Renders the annotations as PGN comment commands, without braces.
+    pub fn to_comment(&self) -> String {
+        let mut res = vec![];
+
+        if !self.marks.is_empty() {
+            res.push(format!(
+                "[%csl {}]",
+                self.marks
+                    .iter()
+                    .map(|m| format!("{}{}", m.tint.letter(), square_name(m.sq)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ));
+        }
+
+        if !self.arrows.is_empty() {
+            res.push(format!(
+                "[%cal {}]",
+                self.arrows
+                    .iter()
+                    .map(|a| {
+                        format!(
+                            "{}{}{}",
+                            a.tint.letter(),
+                            square_name(a.from),
+                            square_name(a.to)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ));
+        }
+
+        res.join(" ")
+    }
+}
+
+/// The `[%cmd args]` commands embedded in a PGN comment.
+fn commands(comment: &str) -> Vec<(&str, &str)> {
+    let mut res = vec![];
+    let mut rest = comment;
+
+    while let Some(start) = rest.find("[%") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let body = &rest[..end];
+        rest = &rest[end + 1..];
+
+        match body.split_once(char::is_whitespace) {
+            Some((cmd, args)) => res.push((cmd, args.trim())),
+            None => res.push((body, "")),
+        }
+    }
+
+    res
+}
+
+pub fn square_name(sq: Square) -> String {
+    let (f, r) = sq.file_rank();
+    format!("{}{}", f.letter(), r.digit())
+}
+
+pub fn parse_square(s: &str) -> Option<Square> {
+    let mut chars = s.chars();
+    let f = chars.next()?;
+    let r = chars.next()?;
+    if chars.next().is_some() || !('a'..='h').contains(&f) || !('1'..='8').contains(&r) {
+        return None;
+    }
+
+    let f = f as u8 - b'a';
+    let r = r as u8 - b'1';
+    Square::new((r * 8 + f) as _)
+}
+
+/// Right-button drawing: a right click toggles a square mark, a right drag
+/// toggles an arrow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AnnotationInput {
+    pub pending: Option<Square>,
+}
+
+impl AnnotationInput {
+    /// Returns whether the event was consumed.
+    pub fn mouse(
+        &mut self,
+        renderer: &BoardRenderer,
+        ev: MouseEvent,
+        annotations: &mut Annotations,
+    ) -> bool {
+        let sq = renderer.translate(ev.row, ev.column);
+        let tint = Tint::from_modifiers(ev.modifiers);
+
+        match ev.kind {
+            MouseEventKind::Down(MouseButton::Right) => {
+                self.pending = sq;
+                true
+            }
+            MouseEventKind::Up(MouseButton::Right) => {
+                match (self.pending.take(), sq) {
+                    (Some(from), Some(to)) if from == to => {
+                        annotations.toggle_mark(Mark { sq: to, tint });
+                    }
+                    (Some(from), Some(to)) => {
+                        annotations.toggle_arrow(Arrow { from, to, tint });
+                    }
+                    _ => {}
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// One game of a PGN file as written, with the main-line comments located
+/// in it. Files are split into games by the same pass that finds the
+/// comments, so both always refer to the same game.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PgnSource {
+    /// Tags and movetext of the game
+    pub text: String,
+    /// Spans of the main-line comments in `text`, braces included, by the
+    /// number of plies played before them
+    pub comments: HashMap<usize, Vec<Range<usize>>>,
+    /// Where a new comment goes in `text` for each ply: after the move and
+    /// its NAGs, or at the start of the movetext before the first move
+    pub anchors: Vec<usize>,
+}
+
+impl PgnSource {
+    /// Splits a PGN file into its games.
+    pub fn split(text: &str) -> Vec<Self> {
+        let mut res = vec![];
+        let mut game = Self::default();
+        let mut start = 0;
+        let mut in_movetext = false;
+        let mut depth = 0;
+
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if !in_movetext && !c.is_whitespace() && c != '[' {
+                in_movetext = true;
+                game.anchors.push(i - start);
+            }
+
+            match c {
+                '[' if depth == 0 => {
+                    if in_movetext {
+                        game.text = text[start..i].trim_end().to_string();
+                        res.push(std::mem::take(&mut game));
+                        start = i;
+                        in_movetext = false;
+                    }
+                    while chars.next().is_some_and(|(_, c)| c != ']') {}
+                }
+                '{' => {
+                    // comments do not nest, the first closing brace ends them
+                    let end = chars
+                        .by_ref()
+                        .find(|(_, c)| *c == '}')
+                        .map_or(text.len(), |(j, _)| j + 1);
+                    if depth == 0 {
+                        let ply = game.anchors.len() - 1;
+                        game.comments
+                            .entry(ply)
+                            .or_default()
+                            .push(i - start..end - start);
+                    }
+                }
+                ';' => while chars.next().is_some_and(|(_, c)| c != '\n') {},
+                '(' => depth += 1,
+                ')' => depth = (depth - 1).max(0),
+                c if c.is_whitespace() => {}
+                _ => {
+                    let mut end = i + c.len_utf8();
+                    while let Some((j, c)) =
+                        chars.next_if(|(_, c)| !c.is_whitespace() && !"{}();[".contains(*c))
+                    {
+                        end = j + c.len_utf8();
+                    }
+                    if depth > 0 {
+                        continue;
+                    }
+
+                    let token = &text[i..end];
+                    let result = matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*");
+                    let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+                    if token.starts_with('$') {
+                        // NAGs stay with their move
+                        if game.anchors.len() > 1 {
+                            *game.anchors.last_mut().unwrap() = end - start;
+                        }
+                    } else if !token.is_empty() && !result {
+                        game.anchors.push(end - start);
+                    }
+                }
+            }
+        }
+
+        if in_movetext {
+            game.text = text[start..].trim_end().to_string();
+            res.push(game);
+        }
+
+        res
+    }
+
+    /// The text of the comments after the given number of plies.
+    pub fn comment(&self, ply: usize) -> String {
+        self.comments
+            .get(&ply)
+            .into_iter()
+            .flatten()
+            .map(|span| self.text[span.start + 1..span.end - 1].trim())
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The game as written, with the `[%cal]` and `[%csl]` commands of its
+    /// main-line comments replaced by `annotations`. Variations, NAGs and
+    /// everything else are kept.
+    pub fn annotate(&self, annotations: &HashMap<usize, Annotations>) -> String {
+        let mut edits: Vec<(Range<usize>, String)> = vec![];
+
+        for (ply, anchor) in self.anchors.iter().enumerate() {
+            let commands = annotations
+                .get(&ply)
+                .map(Annotations::to_comment)
+                .unwrap_or_default();
+
+            let Some(spans) = self.comments.get(&ply) else {
+                if commands.is_empty() {
+                    continue;
+                }
+                edits.push(match ply {
+                    0 => (*anchor..*anchor, format!("{{{commands}}} ")),
+                    _ => (*anchor..*anchor, format!(" {{{commands}}}")),
+                });
+                continue;
+            };
+
+            // the commands go into the first comment, the others lose theirs
+            for (i, span) in spans.iter().enumerate() {
+                let text = strip_commands(&self.text[span.start + 1..span.end - 1]);
+                let text = match (i, text.is_empty(), commands.is_empty()) {
+                    (0, false, false) => format!("{text} {commands}"),
+                    (0, true, false) => commands.clone(),
+                    _ => text,
+                };
+                let text = match text.is_empty() {
+                    true => String::new(),
+                    false => format!("{{{text}}}"),
+                };
+                edits.push((span.clone(), text));
+            }
+        }
+
+        let mut res = self.text.clone();
+        edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+        for (span, text) in edits {
+            res.replace_range(span, &text);
+        }
+        res
+    }
+}
+
+/// A comment with its `[%cal]` and `[%csl]` commands removed, so that they
+/// can be rewritten from [`Annotations::to_comment`].
+pub fn strip_commands(comment: &str) -> String {
+    let mut res = String::new();
+    let mut rest = comment;
+
+    while let Some(start) = rest.find("[%") {
+        let Some(end) = rest[start..].find(']') else {
+            break;
+        };
+        let body = &rest[start + 2..start + end];
+        res += &rest[..start];
+        if !(body.starts_with("cal") || body.starts_with("csl")) {
+            res += &rest[start..=start + end];
+        }
+        rest = &rest[start + end + 1..];
+    }
+    res += rest;
+
+    res.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use mintymacks::model::{BoardFile, BoardRank, Square};
+
+    use super::{Annotations, Arrow, Mark, PgnSource, Tint, strip_commands};
+
+    const GAME: &str = "[Event \"Test\"]\n[Result \"*\"]\n\n\
+        {Start} 1. e4 $1 {Good [%csl Ge4]} e5 (1... c5 {Sicilian [%cal Rc7c5]} 2. Nf3) \
+        2. Nf3 {Develops {not nested [%cal Gg1f3,Bb8c6]} Nc6 *";
+
+    fn sq(file: BoardFile, rank: BoardRank) -> Square {
+        Square::at(file, rank)
+    }
+
+    #[test]
+    fn parse_commands() {
+        let a = Annotations::parse("Threat [%csl Ge4,Rd5] and [%cal Bg1f3, Yb8c6] [%clk 0:01:00]");
+        assert_eq!(
+            a.marks,
+            vec![
+                Mark {
+                    sq: sq(BoardFile::E, BoardRank::_4),
+                    tint: Tint::Green,
+                },
+                Mark {
+                    sq: sq(BoardFile::D, BoardRank::_5),
+                    tint: Tint::Red,
+                },
+            ]
+        );
+        assert_eq!(
+            a.arrows,
+            vec![
+                Arrow {
+                    from: sq(BoardFile::G, BoardRank::_1),
+                    to: sq(BoardFile::F, BoardRank::_3),
+                    tint: Tint::Blue,
+                },
+                Arrow {
+                    from: sq(BoardFile::B, BoardRank::_8),
+                    to: sq(BoardFile::C, BoardRank::_6),
+                    tint: Tint::Yellow,
+                },
+            ]
+        );
+
+        // unknown tints and squares are skipped
+        assert!(Annotations::parse("[%csl Xe4,Gz9] [%cal Ge2]").is_empty());
+    }
+
+    #[test]
+    fn strip() {
+        assert_eq!(
+            strip_commands("Good  [%csl Ge4] move [%clk 0:01:00] [%cal Ge2e4]"),
+            "Good move [%clk 0:01:00]"
+        );
+        assert_eq!(strip_commands("[%csl Ge4]"), "");
+    }
+
+    #[test]
+    fn split_games() {
+        let text = format!("{GAME}\n\n[Event \"Second\"]\n\n1. d4 {{Queen pawn}} *\n");
+        let games = PgnSource::split(&text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].text, GAME);
+        assert!(games[1].text.starts_with("[Event \"Second\"]"));
+        assert_eq!(games[1].comment(1), "Queen pawn");
+    }
+
+    #[test]
+    fn main_line_comments() {
+        let game = &PgnSource::split(GAME)[0];
+
+        assert_eq!(game.comment(0), "Start");
+        assert_eq!(game.comment(1), "Good [%csl Ge4]");
+        // comments in variations belong to no main-line ply
+        assert_eq!(game.comment(2), "");
+        // braces do not nest, so the first closing one ends the comment
+        assert_eq!(game.comment(3), "Develops {not nested [%cal Gg1f3,Bb8c6]");
+        assert_eq!(game.comment(4), "");
+        assert_eq!(game.anchors.len(), 5);
+    }
+
+    #[test]
+    fn unchanged_round_trip() {
+        let game = &PgnSource::split(GAME)[0];
+        let annotations = (0..game.anchors.len())
+            .map(|ply| (ply, Annotations::parse(&game.comment(ply))))
+            .filter(|(_, a)| !a.is_empty())
+            .collect::<HashMap<_, _>>();
+
+        let written = game.annotate(&annotations);
+        assert_eq!(written, GAME);
+        assert_eq!(PgnSource::split(&written)[0], *game);
+    }
+
+    #[test]
+    fn annotate_keeps_variations_and_nags() {
+        let game = &PgnSource::split(GAME)[0];
+        let mut annotations = HashMap::new();
+        annotations.insert(
+            2,
+            Annotations {
+                arrows: vec![],
+                marks: vec![Mark {
+                    sq: sq(BoardFile::E, BoardRank::_5),
+                    tint: Tint::Yellow,
+                }],
+            },
+        );
+
+        let written = game.annotate(&annotations);
+        assert_eq!(
+            written,
+            "[Event \"Test\"]\n[Result \"*\"]\n\n\
+            {Start} 1. e4 $1 {Good} e5 {[%csl Ye5]} (1... c5 {Sicilian [%cal Rc7c5]} 2. Nf3) \
+            2. Nf3 {Develops {not nested} Nc6 *"
+        );
+        let annotated = &PgnSource::split(&written)[0];
+        assert_eq!(Annotations::parse(&annotated.comment(2)), annotations[&2]);
+    }
+}
@@ -8,11 +8,15 @@ use mintymacks::{
     model::{BoardFile, BoardRank, ChessPiece, Color, ColoredChessPiece, Square},
 };
 
+use crate::widgets::annotations::{Annotations, Arrow};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BoardRenderer {
     pub row: u16,
     pub col: u16,
     pub rotated: bool,
+    /// Draw coordinates outside the board instead of inside the edge squares
+    pub frame: bool,
 }
 
 impl BoardRenderer {
@@ -32,22 +36,20 @@ impl BoardRenderer {
         b: 0x77,
     };
 
+    /// Maps a terminal cell to the board square drawn under it.
     pub fn translate(&self, row: u16, col: u16) -> Option<Square> {
-        if row < self.row || self.row + 8 * 5 <= row {
+        if row < self.row || self.row + 8 * 3 <= row {
             return None;
         }
 
-        if col < self.col || self.col + 8 * 3 <= row {
+        if col < self.col || self.col + 8 * 5 <= col {
             return None;
         }
 
-        let row_ix = (row - self.row) / 8;
-        let col_ix = (col - self.col) / 8;
-
-        let sq = Square::at(
-            BoardFile::new(row_ix as i8).unwrap(),
-            BoardRank::new(col_ix as i8).unwrap(),
-        );
+        let sq = (0..64).filter_map(Square::new).find(|&sq| {
+            let (c, r) = self.corner(sq);
+            (c..c + 5).contains(&col) && (r..r + 3).contains(&row)
+        })?;
 
         if self.rotated {
             Some(Self::rotate(sq))
@@ -61,16 +63,28 @@ impl BoardRenderer {
         board: &ArrayBoard<Option<ColoredChessPiece>>,
         highlight: BoardMask,
         selectable: BoardMask,
+    ) -> Vec<u8> {
+        self.render_annotated(board, highlight, selectable, &Annotations::default())
+    }
+
+    pub fn render_annotated(
+        &self,
+        board: &ArrayBoard<Option<ColoredChessPiece>>,
+        highlight: BoardMask,
+        selectable: BoardMask,
+        annotations: &Annotations,
     ) -> Vec<u8> {
         let mut res = vec![];
 
-        let highlight = if self.rotated {
-            highlight.reverse_bits()
+        let (highlight, selectable) = if self.rotated {
+            (highlight.reverse_bits(), selectable.reverse_bits())
         } else {
-            highlight
+            (highlight, selectable)
         };
 
         for (mut sq, pc) in board {
+            let mark = annotations.mark(sq).map(|t| t.color());
+
             if self.rotated {
                 sq = Self::rotate(sq)
             }
@@ -82,13 +96,88 @@ impl BoardRenderer {
                 pc,
                 highlight & sq.bit() != 0,
                 selectable & sq.bit() != 0,
+                mark,
                 &mut res,
             );
         }
 
+        if self.frame {
+            self.frame(&mut res);
+        }
+
+        for arrow in &annotations.arrows {
+            self.arrow(arrow, &mut res);
+        }
+
         res
     }
 
+    /// Draws direction glyphs along the squares an arrow passes over.
+    /// Arrows that are not straight lines only mark their destination.
+    pub fn arrow(&self, arrow: &Arrow, res: &mut Vec<u8>) {
+        let display = |sq| if self.rotated { Self::rotate(sq) } else { sq };
+        let (c1, r1) = self.corner(display(arrow.from));
+        let (c2, r2) = self.corner(display(arrow.to));
+        let dc = (c2 as i32 - c1 as i32) / 5;
+        let dr = (r2 as i32 - r1 as i32) / 3;
+
+        let glyph = match (dc.signum(), dr.signum()) {
+            (1, 0) => '\u{2192}',
+            (-1, 0) => '\u{2190}',
+            (0, -1) => '\u{2191}',
+            (0, 1) => '\u{2193}',
+            (1, -1) => '\u{2197}',
+            (-1, -1) => '\u{2196}',
+            (1, 1) => '\u{2198}',
+            (-1, 1) => '\u{2199}',
+            _ => return,
+        };
+
+        let straight = dc == 0 || dr == 0 || dc.abs() == dr.abs();
+        let path = if straight {
+            (1..=dc.abs().max(dr.abs()))
+                .map(|i| {
+                    (
+                        (c1 as i32 + i * dc.signum() * 5) as u16,
+                        (r1 as i32 + i * dr.signum() * 3) as u16,
+                    )
+                })
+                .collect::<Vec<_>>()
+        } else {
+            vec![(c2, r2)]
+        };
+
+        for (col, row) in path {
+            let Some(sq) = self.translate(row, col) else {
+                continue;
+            };
+            let (_, bg) = Self::colors(sq);
+
+            queue!(
+                res,
+                cursor::MoveTo(col + 1, row + 1),
+                style::PrintStyledContent(glyph.stylize().bold().with(arrow.tint.color()).on(bg)),
+            );
+        }
+    }
+
+    /// Rank digits left of the board and file letters below it.
+    pub fn frame(&self, res: &mut Vec<u8>) {
+        for ix in 0..8 {
+            let sq = Square::at(BoardFile::new(ix).unwrap(), BoardRank::new(ix).unwrap());
+            let lsq = if self.rotated { Self::rotate(sq) } else { sq };
+            let (col, row) = self.corner(sq);
+
+            queue!(
+                res,
+                cursor::MoveTo(self.col.saturating_sub(2), row + 1),
+                style::PrintStyledContent(lsq.file_rank().1.digit().stylize().with(Self::GREY)),
+                cursor::MoveTo(col + 2, self.row + 8 * 3),
+                style::PrintStyledContent(lsq.file_rank().0.letter().stylize().with(Self::GREY)),
+            );
+        }
+    }
+
     pub fn corner(&self, sq: Square) -> (u16, u16) {
         let (f, r) = sq.file_rank();
         let mut f = f.ix() as u16;
@@ -102,13 +191,10 @@ impl BoardRenderer {
         pc: Option<ColoredChessPiece>,
         highlight: bool,
         selected: bool,
+        mark: Option<style::Color>,
         res: &mut Vec<u8>,
     ) {
-        let (mut fg, mut bg) = if sq.bit() & 0x55AA55AA55AA55AA != 0 {
-            (Self::DARK, Self::LIGHT)
-        } else {
-            (Self::LIGHT, Self::DARK)
-        };
+        let (mut fg, mut bg) = Self::colors(sq);
 
         if highlight {
             fg = match fg {
@@ -129,8 +215,12 @@ impl BoardRenderer {
             };
         }
 
+        if let Some(mark) = mark {
+            bg = Self::blend(bg, mark);
+        }
+
         let lsq = if self.rotated { Self::rotate(sq) } else { sq };
-        let line1 = if sq.file_rank().0 == BoardFile::H {
+        let line1 = if sq.file_rank().0 == BoardFile::H && !self.frame {
             format!("    {}", lsq.file_rank().1.digit())
                 .stylize()
                 .bold()
@@ -140,7 +230,7 @@ impl BoardRenderer {
             format!("     ").with(fg).on(bg)
         };
 
-        let line3 = if sq.file_rank().1 == BoardRank::_1 {
+        let line3 = if sq.file_rank().1 == BoardRank::_1 && !self.frame {
             format!("{}    ", lsq.file_rank().0.letter())
                 .stylize()
                 .bold()
@@ -177,6 +267,33 @@ impl BoardRenderer {
         );
     }
 
+    /// Foreground and background of a square, before highlighting.
+    pub fn colors(sq: Square) -> (style::Color, style::Color) {
+        if sq.bit() & 0x55AA55AA55AA55AA != 0 {
+            (Self::DARK, Self::LIGHT)
+        } else {
+            (Self::LIGHT, Self::DARK)
+        }
+    }
+
+    pub fn blend(a: style::Color, b: style::Color) -> style::Color {
+        match (a, b) {
+            (
+                style::Color::Rgb { r, g, b },
+                style::Color::Rgb {
+                    r: r2,
+                    g: g2,
+                    b: b2,
+                },
+            ) => style::Color::Rgb {
+                r: ((r as u16 + r2 as u16) / 2) as u8,
+                g: ((g as u16 + g2 as u16) / 2) as u8,
+                b: ((b as u16 + b2 as u16) / 2) as u8,
+            },
+            _ => a,
+        }
+    }
+
     pub fn rotate(sq: Square) -> Square {
         Square::new(63 - sq.ix()).unwrap()
     }
@@ -204,3 +321,66 @@ impl BoardRenderer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mintymacks::model::{BoardFile, BoardRank, Square};
+
+    use super::BoardRenderer;
+
+    const UPRIGHT: BoardRenderer = BoardRenderer {
+        row: 1,
+        col: 2,
+        rotated: false,
+        frame: false,
+    };
+    const ROTATED: BoardRenderer = BoardRenderer {
+        rotated: true,
+        ..UPRIGHT
+    };
+
+    fn sq(file: BoardFile, rank: BoardRank) -> Option<Square> {
+        Some(Square::at(file, rank))
+    }
+
+    #[test]
+    fn translate_corners() {
+        // every cell of a square maps to it, down to its bottom right
+        assert_eq!(UPRIGHT.translate(1, 2), sq(BoardFile::A, BoardRank::_8));
+        assert_eq!(UPRIGHT.translate(3, 6), sq(BoardFile::A, BoardRank::_8));
+        assert_eq!(UPRIGHT.translate(1, 41), sq(BoardFile::H, BoardRank::_8));
+        assert_eq!(UPRIGHT.translate(24, 2), sq(BoardFile::A, BoardRank::_1));
+        assert_eq!(UPRIGHT.translate(24, 41), sq(BoardFile::H, BoardRank::_1));
+
+        assert_eq!(ROTATED.translate(1, 2), sq(BoardFile::H, BoardRank::_1));
+        assert_eq!(ROTATED.translate(3, 6), sq(BoardFile::H, BoardRank::_1));
+        assert_eq!(ROTATED.translate(1, 41), sq(BoardFile::A, BoardRank::_1));
+        assert_eq!(ROTATED.translate(24, 2), sq(BoardFile::H, BoardRank::_8));
+        assert_eq!(ROTATED.translate(24, 41), sq(BoardFile::A, BoardRank::_8));
+    }
+
+    #[test]
+    fn translate_centre() {
+        assert_eq!(UPRIGHT.translate(12, 21), sq(BoardFile::D, BoardRank::_5));
+        assert_eq!(UPRIGHT.translate(13, 22), sq(BoardFile::E, BoardRank::_4));
+        assert_eq!(UPRIGHT.translate(12, 22), sq(BoardFile::E, BoardRank::_5));
+        assert_eq!(UPRIGHT.translate(13, 21), sq(BoardFile::D, BoardRank::_4));
+
+        assert_eq!(ROTATED.translate(12, 21), sq(BoardFile::E, BoardRank::_4));
+        assert_eq!(ROTATED.translate(13, 22), sq(BoardFile::D, BoardRank::_5));
+        assert_eq!(ROTATED.translate(12, 22), sq(BoardFile::D, BoardRank::_4));
+        assert_eq!(ROTATED.translate(13, 21), sq(BoardFile::E, BoardRank::_5));
+    }
+
+    #[test]
+    fn translate_outside() {
+        for renderer in [UPRIGHT, ROTATED] {
+            assert_eq!(renderer.translate(0, 2), None);
+            assert_eq!(renderer.translate(25, 2), None);
+            assert_eq!(renderer.translate(1, 1), None);
+            assert_eq!(renderer.translate(1, 42), None);
+            assert_eq!(renderer.translate(0, 0), None);
+            assert_eq!(renderer.translate(25, 42), None);
+        }
+    }
+}
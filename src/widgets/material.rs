@@ -0,0 +1,199 @@
+use mintymacks::{
+    arrays::ArrayBoard,
+    model::{ChessPiece, Color, ColoredChessPiece},
+};
+
+use crate::widgets::board::BoardRenderer;
+
+/// Pieces on the board, the pieces captured on the way to it and the
+/// material balance.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Material {
+    pub white: [u8; 6],
+    pub black: [u8; 6],
+    /// White pieces taken by black, in the order they were taken
+    pub white_captured: Vec<ChessPiece>,
+    /// Black pieces taken by white, in the order they were taken
+    pub black_captured: Vec<ChessPiece>,
+}
+
+impl Material {
+    pub const PIECES: [ChessPiece; 6] = {
+        use ChessPiece::*;
+        [Pawn, Knight, Bishop, Rook, Queen, King]
+    };
+
+    pub fn count(board: &ArrayBoard<Option<ColoredChessPiece>>) -> Self {
+        let mut res = Self::default();
+
+        for (_, pc) in board {
+            let Some(pc) = pc else {
+                continue;
+            };
+            let ix = Self::index(pc.piece());
+            match pc.color() {
+                Color::White => res.white[ix] += 1,
+                Color::Black => res.black[ix] += 1,
+            }
+        }
+
+        res
+    }
+
+    /// The material after a move by `mover` led to `board`. Pieces of the
+    /// other side that left the board were captured, while promotions only
+    /// change the pieces of the mover.
+    pub fn after(&self, mover: Color, board: &ArrayBoard<Option<ColoredChessPiece>>) -> Self {
+        let mut res = Self::count(board);
+        res.white_captured = self.white_captured.clone();
+        res.black_captured = self.black_captured.clone();
+
+        let victim = match mover {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        };
+        let (before, after) = (*self.on_board(victim), *res.on_board(victim));
+        let captured = match victim {
+            Color::White => &mut res.white_captured,
+            Color::Black => &mut res.black_captured,
+        };
+        for (ix, pc) in Self::PIECES.iter().enumerate() {
+            for _ in after[ix]..before[ix] {
+                captured.push(*pc);
+            }
+        }
+
+        res
+    }
+
+    pub fn index(pc: ChessPiece) -> usize {
+        Self::PIECES.iter().position(|p| *p == pc).unwrap()
+    }
+
+    pub fn value(pc: ChessPiece) -> i32 {
+        use ChessPiece::*;
+        match pc {
+            Pawn => 1,
+            Knight | Bishop => 3,
+            Rook => 5,
+            Queen => 9,
+            King => 0,
+        }
+    }
+
+    pub fn on_board(&self, c: Color) -> &[u8; 6] {
+        match c {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    /// Pieces of color `c` that were captured.
+    pub fn captured(&self, c: Color) -> &[ChessPiece] {
+        match c {
+            Color::White => &self.white_captured,
+            Color::Black => &self.black_captured,
+        }
+    }
+
+    pub fn total(&self, c: Color) -> i32 {
+        Self::PIECES
+            .iter()
+            .zip(self.on_board(c))
+            .map(|(pc, n)| Self::value(*pc) * *n as i32)
+            .sum()
+    }
+
+    /// Material difference from white's point of view.
+    pub fn balance(&self) -> i32 {
+        self.total(Color::White) - self.total(Color::Black)
+    }
+
+    pub fn render(&self) -> String {
+        let captured = |c: Color| {
+            self.captured(c)
+                .iter()
+                .map(|pc| BoardRenderer::unicode_piece(*pc))
+                .collect::<String>()
+        };
+
+        let balance = match self.balance() {
+            0 => String::from("even"),
+            n if n > 0 => format!("+{n} white"),
+            n => format!("+{} black", -n),
+        };
+
+        format!(
+            "Taken by black: {}\nTaken by white: {}\nMaterial: {}",
+            captured(Color::White),
+            captured(Color::Black),
+            balance,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mintymacks::{
+        bits::board::BitBoard,
+        model::{
+            BoardFile, BoardRank, ChessPiece, Color, Square,
+            moves::{ChessMove, SpecialMove},
+        },
+    };
+
+    use super::Material;
+    use crate::widgets::move_select::{game_from_fen, legal_moves};
+
+    fn play(board: &BitBoard, from: Square, to: Square, promotion: Option<ChessPiece>) -> BitBoard {
+        let mv = legal_moves(board)
+            .into_iter()
+            .find(|mv: &ChessMove| {
+                mv.pmv.from == from
+                    && mv.pmv.to == to
+                    && promotion.is_none_or(|pc| mv.spc == Some(SpecialMove::Promotion(pc)))
+            })
+            .unwrap();
+        let mut res = board.clone();
+        res.apply(mv);
+        res
+    }
+
+    #[test]
+    fn capture() {
+        let board = game_from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        let before = Material::count(&board.render());
+        let board = play(
+            &board,
+            Square::at(BoardFile::E, BoardRank::_4),
+            Square::at(BoardFile::D, BoardRank::_5),
+            None,
+        );
+
+        let after = before.after(Color::White, &board.render());
+        assert_eq!(after.captured(Color::Black), &[ChessPiece::Pawn]);
+        assert!(after.captured(Color::White).is_empty());
+        assert_eq!(after.balance(), 1);
+    }
+
+    #[test]
+    fn promotion_is_not_a_capture() {
+        let board = game_from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1")
+            .unwrap()
+            .board;
+        let before = Material::count(&board.render());
+        let board = play(
+            &board,
+            Square::at(BoardFile::A, BoardRank::_7),
+            Square::at(BoardFile::A, BoardRank::_8),
+            Some(ChessPiece::Queen),
+        );
+
+        let after = before.after(Color::White, &board.render());
+        assert!(after.captured(Color::White).is_empty());
+        assert!(after.captured(Color::Black).is_empty());
+        assert_eq!(after.balance(), 9);
+    }
+}
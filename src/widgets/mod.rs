@@ -1,4 +1,14 @@
-use tokio::io::{AsyncWriteExt, stdout};
+use std::sync::{
+    Once,
+    atomic::{AtomicBool, Ordering},
+};
+
+use tokio::{
+    io::{AsyncWriteExt, stdout},
+    select,
+    signal::unix::{SignalKind, signal},
+    task::JoinHandle,
+};
 
 use crossterm::{
     Command, cursor,
@@ -17,50 +27,132 @@ use mintymacks::{
     notation::MoveMatcher,
 };
 
+pub mod annotations;
 pub mod board;
+pub mod material;
+pub mod move_input;
 pub mod move_select;
 
-static mut SETUP: bool = false;
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+/// Keeps the terminal in raw mode on the alternate screen with mouse
+/// capture for as long as it lives.
+///
+/// The terminal is restored when the guard is dropped, when a panic
+/// unwinds, and before exiting on SIGINT, SIGTERM or SIGHUP. SIGTSTP
+/// suspends the process with the terminal restored, and SIGCONT brings the
+/// TUI back.
+pub struct TerminalGuard {
+    signals: JoinHandle<()>,
+}
+
+impl TerminalGuard {
+    pub fn new() -> tokio::io::Result<Self> {
+        PANIC_HOOK.call_once(|| {
+            let prev = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                restore();
+                prev(info);
+            }));
+        });
 
-pub async fn setup() -> tokio::io::Result<()> {
-    terminal::enable_raw_mode();
-    let mut out = vec![];
+        enter()?;
+
+        Ok(TerminalGuard {
+            signals: tokio::spawn(async {
+                let _ = watch_signals().await;
+            }),
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        self.signals.abort();
+        restore();
+    }
+}
+
+fn enter() -> std::io::Result<()> {
+    terminal::enable_raw_mode()?;
     execute!(
-        out,
+        std::io::stdout(),
         terminal::EnterAlternateScreen,
         cursor::Hide,
         event::EnableMouseCapture,
         terminal::SetTitle("MINTYMACKS")
-    );
-    stdout().write(&out[..]).await?;
-    stdout().flush().await?;
-
-    unsafe {
-        SETUP = true;
-    }
+    )?;
+    ACTIVE.store(true, Ordering::SeqCst);
 
     Ok(())
 }
 
-pub async fn teardown() -> tokio::io::Result<()> {
-    if unsafe { !SETUP } {
-        return Ok(());
+/// Leaves raw mode and the alternate screen, if they are active.
+pub fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
     }
 
-    terminal::disable_raw_mode();
-    let mut out = vec![];
-    execute!(
-        out,
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(
+        std::io::stdout(),
         event::DisableMouseCapture,
         cursor::Show,
         terminal::LeaveAlternateScreen,
     );
-    stdout().write(&out[..]).await?;
-    stdout().flush().await?;
+}
+
+/// Stops the process with the terminal restored, and takes the terminal
+/// back once continued. Raw mode swallows [Ctrl]+[Z], so the TUI calls this
+/// itself when the key is pressed.
+pub fn suspend() {
+    if ACTIVE.load(Ordering::SeqCst) {
+        SUSPENDED.store(true, Ordering::SeqCst);
+    }
+    restore();
+
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+
+    let _ = resume();
+}
+
+/// Takes the terminal back after [`suspend`]. Both the suspending call and
+/// the SIGCONT watcher get here, and only the first one enters.
+fn resume() -> std::io::Result<()> {
+    if SUSPENDED.swap(false, Ordering::SeqCst) {
+        enter()?;
+    }
 
     Ok(())
 }
 
+async fn watch_signals() -> tokio::io::Result<()> {
+    let mut int = signal(SignalKind::interrupt())?;
+    let mut term = signal(SignalKind::terminate())?;
+    let mut hup = signal(SignalKind::hangup())?;
+    let mut tstp = signal(SignalKind::from_raw(libc::SIGTSTP))?;
+    let mut cont = signal(SignalKind::from_raw(libc::SIGCONT))?;
+
+    loop {
+        select! {
+            _ = int.recv() => quit(libc::SIGINT),
+            _ = term.recv() => quit(libc::SIGTERM),
+            _ = hup.recv() => quit(libc::SIGHUP),
+            _ = tstp.recv() => suspend(),
+            _ = cont.recv() => resume()?,
+        }
+    }
+}
+
+fn quit(signo: i32) -> ! {
+    restore();
+    std::process::exit(128 + signo);
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TextRenderer {
     pub row: u16,
@@ -87,4 +179,28 @@ impl TextRenderer {
 
         res
     }
+
+    /// Renders the text inside a frame, covering whatever is beneath it.
+    pub fn render_boxed(self, title: &str, data: &str) -> Vec<u8> {
+        let width = data
+            .lines()
+            .chain([title])
+            .map(|l| l.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut boxed = vec![format!(
+            "\u{250C}\u{2500}{title}{}\u{2510}",
+            "\u{2500}".repeat(width + 1 - title.chars().count())
+        )];
+        for line in data.lines() {
+            boxed.push(format!(
+                "\u{2502} {line}{} \u{2502}",
+                " ".repeat(width - line.chars().count())
+            ));
+        }
+        boxed.push(format!("\u{2514}{}\u{2518}", "\u{2500}".repeat(width + 2)));
+
+        self.render(&boxed.join("\n"))
+    }
 }
@@ -0,0 +1,178 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use mintymacks::{
+    bits::board::BitBoard,
+    game::GameState,
+    model::moves::{ChessMove, SpecialMove},
+    notation::{LongAlg, fen::render_fen},
+};
+use trie_rs::map::{Trie, TrieBuilder};
+
+use crate::widgets::move_select::game_from_fen;
+
+/// Keyboard move entry with completion over the legal moves of a position.
+///
+/// Both SAN (`Nf3`, `exd5`, `O-O`, `e8=Q`) and long algebraic notation
+/// (`g1f3`, `e7e8q`) are accepted. Check and annotation suffixes are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MoveInput {
+    pub buffer: String,
+}
+
+impl MoveInput {
+    pub fn trie(board: &BitBoard, moves: &[ChessMove]) -> Trie<u8, ChessMove> {
+        let game = game_from_fen(&render_fen(board)).ok();
+
+        let mut builder = TrieBuilder::new();
+        for mv in moves {
+            if let Some(san) = game.as_ref().and_then(|game| san(game, *mv)) {
+                builder.push(Self::normalize(&san), *mv);
+            }
+            builder.push(lan(*mv), *mv);
+        }
+        builder.build()
+    }
+
+    pub fn normalize(input: &str) -> String {
+        input
+            .chars()
+            .filter(|c| !matches!(c, '+' | '#' | '!' | '?' | ' '))
+            .map(|c| if c == '0' { 'O' } else { c })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Whether a key press should be routed to the input line rather than
+    /// interpreted as a command.
+    pub fn accepts(&self, key: &KeyEvent) -> bool {
+        if key
+            .modifiers
+            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+        {
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Char(c) if self.is_empty() => {
+                matches!(c, 'a'..='h' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'O' | '0')
+            }
+            KeyCode::Char(_) | KeyCode::Backspace | KeyCode::Tab | KeyCode::Enter => {
+                !self.is_empty()
+            }
+            KeyCode::Esc => !self.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Matching notations and their moves, shortest first.
+    pub fn completions(&self, trie: &Trie<u8, ChessMove>) -> Vec<(String, ChessMove)> {
+        let mut res = trie
+            .predictive_search(Self::normalize(&self.buffer))
+            .map(|(k, v): (String, &ChessMove)| (k, *v))
+            .collect::<Vec<_>>();
+        res.sort_by(|a, b| (a.0.len(), &a.0).cmp(&(b.0.len(), &b.0)));
+        res
+    }
+
+    /// The move the current input denotes, if it is unambiguous.
+    pub fn resolve(&self, trie: &Trie<u8, ChessMove>) -> Option<ChessMove> {
+        if let Some(mv) = trie.exact_match(Self::normalize(&self.buffer)) {
+            return Some(*mv);
+        }
+
+        let completions = self.completions(trie);
+        let (_, first) = completions.first()?;
+        if completions.iter().all(|(_, mv)| mv == first) {
+            Some(*first)
+        } else {
+            None
+        }
+    }
+
+    /// Extends the input to the longest prefix shared by all completions.
+    pub fn complete(&mut self, trie: &Trie<u8, ChessMove>) {
+        let completions = self.completions(trie);
+        let Some((first, _)) = completions.first() else {
+            return;
+        };
+
+        let mut prefix = first.clone();
+        for (k, _) in &completions[1..] {
+            let common = prefix
+                .chars()
+                .zip(k.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            prefix.truncate(common);
+        }
+
+        if prefix.len() > Self::normalize(&self.buffer).len() {
+            self.buffer = prefix;
+        }
+    }
+
+    /// Handles a key accepted by [`Self::accepts`], returning a move when
+    /// [Enter] commits an unambiguous input.
+    pub fn handle(&mut self, key: KeyEvent, trie: &Trie<u8, ChessMove>) -> Option<ChessMove> {
+        match key.code {
+            KeyCode::Char(c) => self.buffer.push(c),
+            KeyCode::Backspace => {
+                self.buffer.pop();
+            }
+            KeyCode::Tab => self.complete(trie),
+            KeyCode::Esc => self.buffer.clear(),
+            KeyCode::Enter => {
+                let res = self.resolve(trie);
+                if res.is_some() {
+                    self.buffer.clear();
+                }
+                return res;
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    pub fn render(&self, trie: &Trie<u8, ChessMove>) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        let completions = self.completions(trie);
+        let hint = match &completions[..] {
+            [] => String::from("(no legal move)"),
+            cs => cs
+                .iter()
+                .map(|(k, _)| &k[..])
+                .take(8)
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+
+        format!("> {}_  {}", self.buffer, hint)
+    }
+}
+
+/// Long algebraic notation as used by UCI.
+pub fn lan(mv: ChessMove) -> String {
+    let promotion = match mv.spc {
+        Some(SpecialMove::Promotion(pc)) => Some(pc),
+        _ => None,
+    };
+    mv.pmv.longalg(promotion)
+}
+
+/// Standard algebraic notation as the library writes it, with any check
+/// or mate suffix.
+pub fn san(game: &GameState, mv: ChessMove) -> Option<String> {
+    let promotion = match mv.spc {
+        Some(SpecialMove::Promotion(pc)) => Some(pc),
+        _ => None,
+    };
+    let mut game = game.clone();
+    let fm = game.find_move((mv.pmv, promotion)).ok()?;
+    Some(game.apply(fm).unwrap().algebraic.to_string())
+}
@@ -1,18 +1,39 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
 use mintymacks::{
     arrays::ArrayBoard,
-    bits::{BoardMask, bit},
+    bits::{BoardMask, bit, board::BitBoard},
+    game::GameState,
     model::{
         BoardRank, ChessPiece, Color, ColoredChessPiece, Dir, Square,
         moves::{ChessMove, SpecialMove},
     },
-    notation::MoveMatcher,
+    notation::{MoveMatcher, pgn::load_pgn_file},
 };
 
-#[derive(Clone, Copy, PartialEq, Eq, Default)]
-struct MoveSelect {
-    origin: Option<Square>,
-    destination: Option<Square>,
-    promotion: Option<Square>,
+use crate::widgets::board::BoardRenderer;
+
+/// All legal moves in the given position.
+pub fn legal_moves(board: &BitBoard) -> Vec<ChessMove> {
+    let mut res = vec![];
+    board.generate_moves(&mut res);
+    res
+}
+
+/// A game starting from the given position.
+pub fn game_from_fen(fen: &str) -> Result<GameState, String> {
+    let pgn = load_pgn_file(&format!("[SetUp \"1\"]\n[FEN \"{fen}\"]\n\n*\n"))
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Invalid FEN: {fen}"))?;
+    GameState::from_pgn(&pgn).map_err(|e| e.to_string())
+}
+
+/// Click and drag-and-drop move entry on a rendered board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MoveSelect {
+    pub origin: Option<Square>,
+    pub destination: Option<Square>,
+    pub promotion: Option<Square>,
 }
 
 impl MoveSelect {
@@ -32,19 +53,105 @@ impl MoveSelect {
         }
     }
 
+    /// Feeds a mouse event over `renderer` into the selection.
+    ///
+    /// A left click selects, and a left button released over a different
+    /// square than the one it was pressed on completes a drag-and-drop.
+    /// Returns the move once the selection identifies exactly one of `moves`.
+    pub fn mouse(
+        &mut self,
+        renderer: &BoardRenderer,
+        ev: MouseEvent,
+        moves: &[ChessMove],
+    ) -> Option<ChessMove> {
+        let sq = renderer.translate(ev.row, ev.column);
+
+        match ev.kind {
+            MouseEventKind::Down(MouseButton::Left) => match sq {
+                Some(sq) => self.click(sq, moves),
+                None => {
+                    self.reset();
+                    None
+                }
+            },
+            MouseEventKind::Up(MouseButton::Left) => match sq {
+                Some(sq) if self.destination.is_none() && self.origin.is_some_and(|o| o != sq) => {
+                    self.click(sq, moves)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Adds a square to the selection, discarding selections that no
+    /// longer match any of `moves`.
+    pub fn click(&mut self, sq: Square, moves: &[ChessMove]) -> Option<ChessMove> {
+        if self.destination.is_none() && self.origin == Some(sq) {
+            self.reset();
+            return None;
+        }
+
+        self.add(sq);
+
+        let candidates = moves
+            .iter()
+            .copied()
+            .filter(|mv| self.matches(*mv))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            let retry = self.destination.is_some() && self.promotion.is_none();
+            self.reset();
+            // clicking another own piece instead of a destination reselects
+            if retry {
+                return self.click(sq, moves);
+            }
+            return None;
+        }
+
+        if self.destination.is_none() {
+            return None;
+        }
+
+        match &candidates[..] {
+            [mv] => {
+                self.reset();
+                Some(*mv)
+            }
+            _ => None,
+        }
+    }
+
+    /// Squares that may be clicked next.
+    pub fn selectable(&self, moves: &[ChessMove]) -> BoardMask {
+        match (self.origin, self.destination) {
+            (None, _) => moves.iter().fold(0, |acc, mv| acc | mv.pmv.from.bit()),
+            (Some(_), None) => moves
+                .iter()
+                .filter(|mv| self.matches(**mv))
+                .fold(0, |acc, mv| acc | mv.pmv.to.bit()),
+            _ => 0,
+        }
+    }
+
+    /// Draws the promotion picker onto `board` when a promotion is pending.
     pub fn show_promotion(
         self,
         board: &mut ArrayBoard<Option<ColoredChessPiece>>,
     ) -> Option<BoardMask> {
         use ChessPiece::*;
 
+        if self.promotion.is_some() {
+            return None;
+        }
         let Some(dest) = self.destination else {
             return None;
         };
         let (dir, col) = if dest.file_rank().1 == BoardRank::_8 {
             (Dir::South, Color::White)
         } else if dest.file_rank().1 == BoardRank::_1 {
-            (Dir::South, Color::Black)
+            (Dir::North, Color::Black)
         } else {
             return None;
         };
@@ -62,10 +169,13 @@ impl MoveSelect {
         )
     }
 
-    pub fn promotion(self) -> Option<SpecialMove> {
+    pub fn promotion(self) -> Option<ChessPiece> {
         let Some(dest) = self.destination else {
             return None;
         };
+        let Some(promotion) = self.promotion else {
+            return None;
+        };
         let dir = if dest.file_rank().1 == BoardRank::_8 {
             Dir::South
         } else if dest.file_rank().1 == BoardRank::_1 {
@@ -74,16 +184,16 @@ impl MoveSelect {
             return None;
         };
 
-        if self.promotion == dest.go(&[]) {
-            Some(SpecialMove::Promotion(ChessPiece::Queen))
-        } else if self.promotion == dest.go(&[dir]) {
-            Some(SpecialMove::Promotion(ChessPiece::Rook))
-        } else if self.promotion == dest.go(&[dir, dir]) {
-            Some(SpecialMove::Promotion(ChessPiece::Bishop))
-        } else if self.promotion == dest.go(&[dir, dir, dir]) {
-            Some(SpecialMove::Promotion(ChessPiece::Knight))
+        if Some(promotion) == dest.go(&[]) {
+            Some(ChessPiece::Queen)
+        } else if Some(promotion) == dest.go(&[dir]) {
+            Some(ChessPiece::Rook)
+        } else if Some(promotion) == dest.go(&[dir, dir]) {
+            Some(ChessPiece::Bishop)
+        } else if Some(promotion) == dest.go(&[dir, dir, dir]) {
+            Some(ChessPiece::Knight)
         } else {
-            Some(SpecialMove::Null)
+            None
         }
     }
 }
@@ -92,6 +202,220 @@ impl MoveMatcher for MoveSelect {
     fn matches(&self, mv: ChessMove) -> bool {
         Some(mv.pmv.from) == self.origin
             && (Some(mv.pmv.to) == self.destination || self.destination.is_none())
-            && (mv.spc == self.promotion() || self.promotion().is_none())
+            && match (self.promotion, self.promotion()) {
+                (None, _) => true,
+                (Some(_), Some(pc)) => mv.spc == Some(SpecialMove::Promotion(pc)),
+                (Some(_), None) => false,
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use mintymacks::{
+        bits::board::BitBoard,
+        model::{
+            BoardFile, BoardRank, ChessPiece, Square,
+            moves::{ChessMove, SpecialMove},
+        },
+    };
+
+    use super::{MoveSelect, game_from_fen, legal_moves};
+    use crate::widgets::board::BoardRenderer;
+
+    const RENDERER: BoardRenderer = BoardRenderer {
+        row: 0,
+        col: 0,
+        rotated: false,
+        frame: false,
+    };
+
+    fn board(fen: &str) -> BitBoard {
+        game_from_fen(fen).unwrap().board
+    }
+
+    /// Origin, destination and promotion of a move.
+    fn parts(mv: ChessMove) -> (Square, Square, Option<ChessPiece>) {
+        let promotion = match mv.spc {
+            Some(SpecialMove::Promotion(pc)) => Some(pc),
+            _ => None,
+        };
+        (mv.pmv.from, mv.pmv.to, promotion)
+    }
+
+    fn sq(file: BoardFile, rank: BoardRank) -> Square {
+        Square::at(file, rank)
+    }
+
+    /// A mouse event in the middle of a square.
+    fn mouse(kind: MouseEventKind, sq: Square) -> MouseEvent {
+        let (column, row) = RENDERER.corner(sq);
+        MouseEvent {
+            kind,
+            column: column + 2,
+            row: row + 1,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn click_to_select() {
+        let moves = legal_moves(&board(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ));
+        let mut select = MoveSelect::default();
+
+        assert_eq!(select.click(sq(BoardFile::E, BoardRank::_2), &moves), None);
+        assert_eq!(select.origin, Some(sq(BoardFile::E, BoardRank::_2)));
+
+        let mv = select.click(sq(BoardFile::E, BoardRank::_4), &moves);
+        assert_eq!(
+            mv.map(parts),
+            Some((
+                sq(BoardFile::E, BoardRank::_2),
+                sq(BoardFile::E, BoardRank::_4),
+                None
+            ))
+        );
+        assert_eq!(select, MoveSelect::default());
+    }
+
+    #[test]
+    fn click_reselects_and_deselects() {
+        let moves = legal_moves(&board(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ));
+        let mut select = MoveSelect::default();
+
+        select.click(sq(BoardFile::E, BoardRank::_2), &moves);
+        assert_eq!(select.click(sq(BoardFile::G, BoardRank::_1), &moves), None);
+        assert_eq!(select.origin, Some(sq(BoardFile::G, BoardRank::_1)));
+
+        select.click(sq(BoardFile::G, BoardRank::_1), &moves);
+        assert_eq!(select, MoveSelect::default());
+    }
+
+    #[test]
+    fn drag_and_drop() {
+        let moves = legal_moves(&board(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ));
+        let mut select = MoveSelect::default();
+
+        let down = mouse(
+            MouseEventKind::Down(MouseButton::Left),
+            sq(BoardFile::G, BoardRank::_1),
+        );
+        assert_eq!(select.mouse(&RENDERER, down, &moves), None);
+
+        let up = mouse(
+            MouseEventKind::Up(MouseButton::Left),
+            sq(BoardFile::F, BoardRank::_3),
+        );
+        let mv = select.mouse(&RENDERER, up, &moves);
+        assert_eq!(
+            mv.map(parts),
+            Some((
+                sq(BoardFile::G, BoardRank::_1),
+                sq(BoardFile::F, BoardRank::_3),
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn release_on_origin_keeps_selection() {
+        let moves = legal_moves(&board(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ));
+        let mut select = MoveSelect::default();
+        let e2 = sq(BoardFile::E, BoardRank::_2);
+
+        select.mouse(
+            &RENDERER,
+            mouse(MouseEventKind::Down(MouseButton::Left), e2),
+            &moves,
+        );
+        select.mouse(
+            &RENDERER,
+            mouse(MouseEventKind::Up(MouseButton::Left), e2),
+            &moves,
+        );
+        assert_eq!(select.origin, Some(e2));
+    }
+
+    #[test]
+    fn white_promotion_picker() {
+        let position = board("8/P7/8/8/8/8/8/k6K w - - 0 1");
+        let moves = legal_moves(&position);
+        let mut select = MoveSelect::default();
+
+        select.click(sq(BoardFile::A, BoardRank::_7), &moves);
+        assert_eq!(select.click(sq(BoardFile::A, BoardRank::_8), &moves), None);
+
+        let mut pieces = position.render();
+        let picker = select.show_promotion(&mut pieces).unwrap();
+        for rank in [BoardRank::_8, BoardRank::_7, BoardRank::_6, BoardRank::_5] {
+            assert_ne!(picker & sq(BoardFile::A, rank).bit(), 0);
+        }
+
+        // queen, rook, bishop and knight are picked going south
+        let mv = select.click(sq(BoardFile::A, BoardRank::_7), &moves);
+        assert_eq!(
+            mv.map(parts),
+            Some((
+                sq(BoardFile::A, BoardRank::_7),
+                sq(BoardFile::A, BoardRank::_8),
+                Some(ChessPiece::Rook)
+            ))
+        );
+    }
+
+    #[test]
+    fn black_promotion_picker() {
+        let position = board("K7/8/8/8/8/8/p7/7k b - - 0 1");
+        let moves = legal_moves(&position);
+        let mut select = MoveSelect::default();
+
+        let down = mouse(
+            MouseEventKind::Down(MouseButton::Left),
+            sq(BoardFile::A, BoardRank::_2),
+        );
+        let up = mouse(
+            MouseEventKind::Up(MouseButton::Left),
+            sq(BoardFile::A, BoardRank::_1),
+        );
+        select.mouse(&RENDERER, down, &moves);
+        assert_eq!(select.mouse(&RENDERER, up, &moves), None);
+
+        let mut pieces = position.render();
+        let picker = select.show_promotion(&mut pieces).unwrap();
+        for rank in [BoardRank::_1, BoardRank::_2, BoardRank::_3, BoardRank::_4] {
+            assert_ne!(picker & sq(BoardFile::A, rank).bit(), 0);
+        }
+
+        // going north for black
+        let mv = select.click(sq(BoardFile::A, BoardRank::_4), &moves);
+        assert_eq!(
+            mv.map(parts),
+            Some((
+                sq(BoardFile::A, BoardRank::_2),
+                sq(BoardFile::A, BoardRank::_1),
+                Some(ChessPiece::Knight)
+            ))
+        );
+        let mut select = MoveSelect::default();
+        select.click(sq(BoardFile::A, BoardRank::_2), &moves);
+        select.click(sq(BoardFile::A, BoardRank::_1), &moves);
+        let mv = select.click(sq(BoardFile::A, BoardRank::_1), &moves);
+        assert_eq!(
+            mv.map(parts),
+            Some((
+                sq(BoardFile::A, BoardRank::_2),
+                sq(BoardFile::A, BoardRank::_1),
+                Some(ChessPiece::Queen)
+            ))
+        );
     }
 }